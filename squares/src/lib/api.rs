@@ -2,27 +2,47 @@
 pub struct Cell {
     pub row: i32,
     pub column: i32,
-    pub red: u8,
-    pub green: u8,
-    pub blue: u8,
+    #[serde(flatten)]
+    pub color: Color,
+    pub blend: Option<String>,
+    pub layer: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct Line {
     pub row: i32,
     pub column: i32,
-    pub red: u8,
-    pub green: u8,
-    pub blue: u8,
+    #[serde(flatten)]
+    pub color: Color,
     pub direction: i32,
     pub length: i32,
+    pub blend: Option<String>,
+    pub layer: Option<String>,
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct ApiGrid {
     pub zero_row: i32,
     pub zero_column: i32,
-    pub api_grid: [[RGB; 8]; 8],
+    pub api_grid: Option<[[Color; 8]; 8]>,
+    //rgb24 rows encoded as base64, instead of api_grid
+    pub width: Option<usize>,
+    pub data: Option<String>,
+    pub blend: Option<String>,
+    pub layer: Option<String>,
+}
+
+//any of the accepted color formats, converted by lib::color::resolve
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Color {
+    Rgb(RGB),
+    Hsv(HSV),
+    Hsl(HSL),
+    Named(NamedColor),
+    Index(PaletteIndex),
+    Indexed(u8),
+    Text(String),
 }
 
 #[derive(Serialize, Deserialize)]
@@ -30,4 +50,105 @@ pub struct RGB {
     pub red: u8,
     pub green: u8,
     pub blue: u8,
+    pub alpha: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HSV {
+    pub h: f64,
+    pub s: f64,
+    pub v: f64,
+    pub alpha: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct HSL {
+    pub h: f64,
+    pub s: f64,
+    pub l: f64,
+    pub alpha: Option<u8>,
+}
+
+//a color of the active palette
+#[derive(Serialize, Deserialize)]
+pub struct PaletteIndex {
+    pub index: u8,
+    pub alpha: Option<u8>,
+}
+
+//a hex string like "#ff44cc" or a css color name
+#[derive(Serialize, Deserialize)]
+pub struct NamedColor {
+    pub color: String,
+    pub alpha: Option<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Clear {
+    pub color: Option<Color>,
+    pub pattern: Option<String>,
+    pub layer: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ApiPattern {
+    pub name: String,
+    pub color: Option<Color>,
+    pub second_color: Option<Color>,
+    pub size: Option<usize>,
+    pub seed: Option<u32>,
+    pub layer: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ApiAutomaton {
+    pub rule: Option<String>,
+    pub interval: Option<u64>,
+    pub layer: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ApiText {
+    pub text: String,
+    pub row: i32,
+    pub column: Option<i32>,
+    pub color: Color,
+    pub scroll: Option<bool>,
+    pub interval: Option<u64>,
+    pub layer: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ApiLayer {
+    pub name: String,
+    pub z_index: Option<i32>,
+    pub visible: Option<bool>,
+    pub opacity: Option<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ApiPalette {
+    pub name: String,
+    pub colors: Vec<Color>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ApiPaletteFile {
+    pub path: String,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ApiPaletteCycle {
+    pub shift: i32,
+}
+
+//what is shown during an intervention, a checkerboard if nothing is given
+#[derive(Serialize, Deserialize)]
+pub struct ApiIntervention {
+    pub color: Option<Color>,
+    pub pattern: Option<ApiPattern>,
+    pub image: Option<String>,
+    pub text: Option<ApiText>,
+    //seconds until the program resumes on its own
+    pub timeout: Option<u64>,
 }
@@ -0,0 +1,183 @@
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::lib::auth::Client;
+
+//entries that are kept in memory for the history of the cells
+const HISTORY: usize = 10000;
+
+//cells that were changed by a request
+#[derive(Clone, Copy, Serialize)]
+pub struct Rectangle {
+    pub row: i32,
+    pub column: i32,
+    pub rows: i32,
+    pub columns: i32,
+}
+
+#[derive(Clone, Serialize)]
+pub struct Entry {
+    //milliseconds since the unix epoch
+    pub time: u64,
+    pub client: String,
+    pub operation: String,
+    pub cells: Vec<Rectangle>,
+}
+
+pub struct AuditSettings {
+    //without a path the entries are only kept in memory
+    pub path: Option<PathBuf>,
+    //the file is rotated when it grows larger
+    pub max_bytes: u64,
+    //how many rotated files are kept, as <path>.1 to <path>.<keep>
+    pub keep: usize,
+}
+
+pub struct Audit {
+    settings: AuditSettings,
+    file: Option<File>,
+    written: u64,
+    history: VecDeque<Entry>,
+}
+
+pub struct SharedAudit {
+    pub audit: Arc<Mutex<Audit>>,
+}
+
+pub fn audit_init(settings: AuditSettings) -> std::io::Result<SharedAudit> {
+    let (file, written) = match &settings.path {
+        Some(path) => {
+            let file = OpenOptions::new().create(true).append(true).open(path)?;
+            let written = file.metadata()?.len();
+            (Some(file), written)
+        }
+        None => (None, 0),
+    };
+
+    Ok(SharedAudit {
+        audit: Arc::new(Mutex::new(Audit {
+            settings,
+            file,
+            written,
+            history: VecDeque::new(),
+        })),
+    })
+}
+
+pub fn rectangle(row: i32, column: i32, rows: i32, columns: i32) -> Rectangle {
+    Rectangle {
+        row,
+        column,
+        rows,
+        columns,
+    }
+}
+
+//appends one line to the log, a failing log file does not stop the request
+pub fn record(shared_audit: &SharedAudit, client: &Client, operation: &str, cells: Vec<Rectangle>) {
+    let time = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis()),
+        Err(_) => 0,
+    };
+    let entry = Entry {
+        time,
+        client: client.name.clone(),
+        operation: String::from(operation),
+        cells,
+    };
+
+    let mut audit = shared_audit.audit.lock().expect("audit lock failed");
+    if let Err(error) = write_entry(&mut audit, &entry) {
+        println!("Could not write the audit log: {}", error);
+    }
+
+    if audit.history.len() == HISTORY {
+        audit.history.pop_front();
+    }
+    audit.history.push_back(entry);
+}
+
+fn write_entry(audit: &mut Audit, entry: &Entry) -> std::io::Result<()> {
+    let mut line = serde_json::to_vec(entry)?;
+    line.push(b'\n');
+
+    if audit.written + line.len() as u64 > audit.settings.max_bytes && audit.written > 0 {
+        //the entry still goes to the old file, rotating is tried again with the next one
+        if let Err(error) = rotate(audit) {
+            println!("Could not rotate the audit log: {}", error);
+        }
+    }
+    if let Some(file) = &mut audit.file {
+        file.write_all(&line)?;
+        audit.written += line.len() as u64;
+    }
+    Ok(())
+}
+
+//waits for the request that is writing and makes sure the log is on disk
+pub fn flush(shared_audit: &SharedAudit) -> std::io::Result<()> {
+    let audit = shared_audit.audit.lock().expect("audit lock failed");
+    if let Some(file) = &audit.file {
+        file.sync_all()?;
+    }
+    Ok(())
+}
+
+//<path>.1 becomes <path>.2 and so on, the oldest file is dropped. The old file is only
+//replaced once the new one is open.
+fn rotate(audit: &mut Audit) -> std::io::Result<()> {
+    let path = match &audit.settings.path {
+        Some(path) => path.clone(),
+        None => return Ok(()),
+    };
+
+    let file = if audit.settings.keep == 0 {
+        OpenOptions::new()
+            .create(true)
+            .write(true)
+            .truncate(true)
+            .open(&path)?
+    } else {
+        for i in (1..audit.settings.keep).rev() {
+            let older = rotated(&path, i);
+            if older.exists() {
+                fs::rename(older, rotated(&path, i + 1))?;
+            }
+        }
+        fs::rename(&path, rotated(&path, 1))?;
+        OpenOptions::new().create(true).append(true).open(&path)?
+    };
+
+    audit.file = Some(file);
+    audit.written = 0;
+    Ok(())
+}
+
+fn rotated(path: &Path, i: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", i));
+    PathBuf::from(name)
+}
+
+//who changed the cell when, the latest change first
+pub fn history(shared_audit: &SharedAudit, row: i32, column: i32) -> Vec<Entry> {
+    let audit = shared_audit.audit.lock().expect("audit lock failed");
+    audit
+        .history
+        .iter()
+        .rev()
+        .filter(|entry| {
+            entry.cells.iter().any(|cells| {
+                row >= cells.row
+                    && i64::from(row) < i64::from(cells.row) + i64::from(cells.rows)
+                    && column >= cells.column
+                    && i64::from(column) < i64::from(cells.column) + i64::from(cells.columns)
+            })
+        })
+        .cloned()
+        .collect()
+}
@@ -0,0 +1,201 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use rocket::http::Status;
+use rocket::request::{self, FromRequest, Request};
+use rocket::{Outcome, State};
+
+use crate::lib::limits::{self, SharedLimits};
+use crate::lib::shutdown::{self, SharedShutdown};
+
+//every scope includes the scopes before it
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Scope {
+    Read,
+    Draw,
+    Admin,
+}
+
+//who sent a request, the name of the token or the ip address without tokens
+#[derive(Clone, Debug)]
+pub struct Client {
+    pub name: String,
+    pub scope: Scope,
+}
+
+struct Token {
+    token: String,
+    client: Client,
+}
+
+//without a tokens file every request is allowed
+pub struct Tokens {
+    tokens: Option<Vec<Token>>,
+}
+
+//request guards, a request without a token of the scope is answered with 401 or 403,
+//clients that sent too many requests with 429
+pub struct ReadAccess(pub Client);
+pub struct DrawAccess(pub Client);
+pub struct AdminAccess(pub Client);
+
+pub fn no_tokens() -> Tokens {
+    Tokens { tokens: None }
+}
+
+//one token per line: <token> <read|draw|admin> [name], lines starting with # are skipped
+pub fn load_tokens(path: &Path) -> std::io::Result<Tokens> {
+    let contents = fs::read_to_string(path)?;
+    let mut tokens = Vec::new();
+
+    for line in contents.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if words.len() < 2 {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("Invalid token line: {}", line),
+            ));
+        }
+        let name = match words.get(2) {
+            Some(name) => name,
+            None => words[0],
+        };
+        tokens.push(Token {
+            token: String::from(words[0]),
+            client: Client {
+                name: String::from(name),
+                scope: scope_from_name(words[1])?,
+            },
+        });
+    }
+    Ok(Tokens {
+        tokens: Some(tokens),
+    })
+}
+
+pub fn scope_from_name(name: &str) -> std::io::Result<Scope> {
+    match name {
+        "read" => Ok(Scope::Read),
+        "draw" => Ok(Scope::Draw),
+        "admin" => Ok(Scope::Admin),
+        _ => Err(Error::new(
+            ErrorKind::Other,
+            format!("Unknown scope: {}", name),
+        )),
+    }
+}
+
+fn authorize(request: &Request, scope: Scope) -> request::Outcome<Client, ()> {
+    //no request is accepted anymore once the program is stopping
+    match request.guard::<State<SharedShutdown>>() {
+        Outcome::Success(shared_shutdown) => {
+            if shutdown::is_requested(shared_shutdown.inner()) {
+                return Outcome::Failure((Status::ServiceUnavailable, ()));
+            }
+        }
+        _ => return Outcome::Failure((Status::InternalServerError, ())),
+    }
+
+    let client = match check_token(request, scope) {
+        Outcome::Success(client) => client,
+        Outcome::Failure(failure) => return Outcome::Failure(failure),
+        Outcome::Forward(forward) => return Outcome::Forward(forward),
+    };
+
+    let shared_limits = match request.guard::<State<SharedLimits>>() {
+        Outcome::Success(shared_limits) => shared_limits.inner(),
+        _ => return Outcome::Failure((Status::InternalServerError, ())),
+    };
+    if limits::take_request(shared_limits, &client.name) {
+        Outcome::Success(client)
+    } else {
+        Outcome::Failure((Status::TooManyRequests, ()))
+    }
+}
+
+//compares every byte, so the time it takes does not tell how much of a guess was right
+fn same_token(token: &str, guess: &str) -> bool {
+    let (token, guess) = (token.as_bytes(), guess.as_bytes());
+    token.len() == guess.len()
+        && token
+            .iter()
+            .zip(guess)
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+//checks the "Authorization: Bearer <token>" header
+fn check_token(request: &Request, scope: Scope) -> request::Outcome<Client, ()> {
+    let tokens = match request.guard::<State<Tokens>>() {
+        Outcome::Success(tokens) => tokens.inner(),
+        _ => return Outcome::Failure((Status::InternalServerError, ())),
+    };
+    let tokens = match &tokens.tokens {
+        Some(tokens) => tokens,
+        None => {
+            let name = match request.client_ip() {
+                Some(ip) => ip.to_string(),
+                None => String::from("anonymous"),
+            };
+            return Outcome::Success(Client {
+                name,
+                scope: Scope::Admin,
+            });
+        }
+    };
+
+    let header = request.headers().get_one("Authorization").unwrap_or("");
+    let bearer = if header.starts_with("Bearer ") {
+        header[7..].trim()
+    } else {
+        ""
+    };
+
+    match tokens.iter().find(|token| same_token(&token.token, bearer)) {
+        Some(token) if token.client.scope >= scope => Outcome::Success(token.client.clone()),
+        Some(_) => Outcome::Failure((Status::Forbidden, ())),
+        None => Outcome::Failure((Status::Unauthorized, ())),
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for ReadAccess {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, ()> {
+        match authorize(request, Scope::Read) {
+            Outcome::Success(client) => Outcome::Success(ReadAccess(client)),
+            Outcome::Failure(failure) => Outcome::Failure(failure),
+            Outcome::Forward(forward) => Outcome::Forward(forward),
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for DrawAccess {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, ()> {
+        match authorize(request, Scope::Draw) {
+            Outcome::Success(client) => Outcome::Success(DrawAccess(client)),
+            Outcome::Failure(failure) => Outcome::Failure(failure),
+            Outcome::Forward(forward) => Outcome::Forward(forward),
+        }
+    }
+}
+
+impl<'a, 'r> FromRequest<'a, 'r> for AdminAccess {
+    type Error = ();
+
+    fn from_request(request: &'a Request<'r>) -> request::Outcome<Self, ()> {
+        match authorize(request, Scope::Admin) {
+            Outcome::Success(client) => Outcome::Success(AdminAccess(client)),
+            Outcome::Failure(failure) => Outcome::Failure(failure),
+            Outcome::Forward(forward) => Outcome::Forward(forward),
+        }
+    }
+}
@@ -0,0 +1,215 @@
+use std::io::{Error, ErrorKind};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::lib::data::{Grid, SharedGrid, RGB};
+use crate::lib::layers;
+
+//Life like rules in B/S notation, or the rule number of an elementary automaton
+pub enum Rule {
+    Life {
+        birth: [bool; 9],
+        survival: [bool; 9],
+    },
+    Elementary(u8),
+}
+
+pub struct Automaton {
+    pub rule: Rule,
+    pub running: bool,
+    pub interval: Duration,
+    pub last_step: Instant,
+    pub layer: Option<String>,
+}
+
+pub struct SharedAutomaton {
+    pub automaton: Arc<Mutex<Automaton>>,
+}
+
+pub fn automaton_init() -> SharedAutomaton {
+    let automaton = Automaton {
+        rule: parse_rule("B3/S23").expect("default rule is valid"),
+        running: false,
+        interval: Duration::from_millis(200),
+        last_step: Instant::now(),
+        layer: None,
+    };
+
+    SharedAutomaton {
+        automaton: Arc::new(Mutex::new(automaton)),
+    }
+}
+
+//accepts "B3/S23" for Life like rules and "W30" for elementary automata
+pub fn parse_rule(rule: &str) -> std::io::Result<Rule> {
+    let rule = rule.trim().to_uppercase();
+
+    if rule.starts_with('W') {
+        return match rule[1..].parse::<u8>() {
+            Ok(number) => Ok(Rule::Elementary(number)),
+            Err(_) => Err(Error::new(
+                ErrorKind::Other,
+                "Elementary rule must be a number between 0 and 255",
+            )),
+        };
+    }
+
+    let parts: Vec<&str> = rule.split('/').collect();
+    if parts.len() != 2 || !parts[0].starts_with('B') || !parts[1].starts_with('S') {
+        return Err(Error::new(
+            ErrorKind::Other,
+            "Rule must look like B3/S23 or W30",
+        ));
+    }
+
+    Ok(Rule::Life {
+        birth: parse_counts(&parts[0][1..])?,
+        survival: parse_counts(&parts[1][1..])?,
+    })
+}
+
+fn parse_counts(digits: &str) -> std::io::Result<[bool; 9]> {
+    let mut counts = [false; 9];
+    for digit in digits.chars() {
+        match digit.to_digit(10) {
+            Some(count) if count < 9 => counts[count as usize] = true,
+            _ => {
+                return Err(Error::new(
+                    ErrorKind::Other,
+                    "Neighbour counts must be between 0 and 8",
+                ))
+            }
+        }
+    }
+    Ok(counts)
+}
+
+//called by the render loop, steps the grid once the interval has passed
+pub fn tick(shared_automaton: &SharedAutomaton, shared_grid: &SharedGrid) {
+    let mut automaton = shared_automaton
+        .automaton
+        .lock()
+        .expect("automaton lock failed");
+
+    if automaton.running && automaton.last_step.elapsed() >= automaton.interval {
+        let mut sharedgrid_data = shared_grid.sharedgrid.lock().expect("grid lock failed");
+        if let Ok(grid_data) = layers::layer_mut(&mut sharedgrid_data, &automaton.layer) {
+            step(grid_data, &automaton.rule);
+        }
+        automaton.last_step = Instant::now();
+    }
+}
+
+//cells that differ from the background are alive, cells that die become transparent
+pub fn step(grid: &mut Grid, rule: &Rule) {
+    match rule {
+        Rule::Life { birth, survival } => step_life(grid, birth, survival),
+        Rule::Elementary(number) => step_elementary(grid, *number),
+    }
+}
+
+fn step_life(grid: &mut Grid, birth: &[bool; 9], survival: &[bool; 9]) {
+    let background = grid.background;
+    let max_rows = grid.grid.len();
+    let max_columns = grid.grid[0].len();
+    let mut next = grid.grid.clone();
+    let mut opaque = grid.opaque.clone();
+
+    for row in 0..max_rows {
+        for column in 0..max_columns {
+            let mut neighbours = Vec::new();
+            for d_row in [max_rows - 1, 0, 1].iter() {
+                for d_column in [max_columns - 1, 0, 1].iter() {
+                    if *d_row == 0 && *d_column == 0 {
+                        continue;
+                    }
+                    let color =
+                        grid.grid[(row + d_row) % max_rows][(column + d_column) % max_columns];
+                    if color != background {
+                        neighbours.push(color);
+                    }
+                }
+            }
+
+            let alive = grid.grid[row][column] != background;
+            if alive && survival[neighbours.len()] {
+                continue;
+            } else if !alive && birth[neighbours.len()] {
+                next[row][column] = average(&neighbours, background);
+                opaque[row][column] = true;
+            } else if alive {
+                next[row][column] = background;
+                opaque[row][column] = false;
+            }
+        }
+    }
+
+    grid.grid = next;
+    grid.opaque = opaque;
+}
+
+//the bottom row is the current generation, the grid scrolls up by one row per step
+fn step_elementary(grid: &mut Grid, number: u8) {
+    let background = grid.background;
+    let max_columns = grid.grid[0].len();
+    let last = grid.grid[grid.grid.len() - 1].clone();
+    let mut next = Vec::with_capacity(max_columns);
+    let mut next_opaque = Vec::with_capacity(max_columns);
+
+    for column in 0..max_columns {
+        let left = last[(column + max_columns - 1) % max_columns];
+        let center = last[column];
+        let right = last[(column + 1) % max_columns];
+
+        let mut parents = Vec::new();
+        let mut pattern = 0;
+        for color in [left, center, right].iter() {
+            pattern <<= 1;
+            if *color != background {
+                pattern |= 1;
+                parents.push(*color);
+            }
+        }
+
+        let born = (number >> pattern) & 1 == 1;
+        if born {
+            next.push(average(&parents, background));
+        } else {
+            next.push(background);
+        }
+        next_opaque.push(born);
+    }
+
+    grid.grid.remove(0);
+    grid.grid.push(next);
+    grid.indices.remove(0);
+    grid.indices.push(vec![None; max_columns]);
+    grid.opaque.remove(0);
+    grid.opaque.push(next_opaque);
+}
+
+//a cell born without living parents takes the inverted background color
+fn average(colors: &[RGB], background: RGB) -> RGB {
+    if colors.is_empty() {
+        return RGB {
+            red: 255 - background.red,
+            green: 255 - background.green,
+            blue: 255 - background.blue,
+        };
+    }
+
+    let count = colors.len() as u32;
+    let sum = colors.iter().fold((0, 0, 0), |sum, color| {
+        (
+            sum.0 + color.red as u32,
+            sum.1 + color.green as u32,
+            sum.2 + color.blue as u32,
+        )
+    });
+
+    RGB {
+        red: (sum.0 / count) as u8,
+        green: (sum.1 / count) as u8,
+        blue: (sum.2 / count) as u8,
+    }
+}
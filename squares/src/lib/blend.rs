@@ -0,0 +1,55 @@
+use std::io::{Error, ErrorKind};
+
+use crate::lib::data::{Grid, RGB};
+
+pub enum BlendMode {
+    Normal,
+    Add,
+    Multiply,
+    Screen,
+    Xor,
+}
+
+//no name means a normal, opaque write
+pub fn blend_mode_from_name(name: &Option<String>) -> std::io::Result<BlendMode> {
+    let name = match name {
+        Some(name) => name.as_str(),
+        None => "normal",
+    };
+
+    match name {
+        "normal" => Ok(BlendMode::Normal),
+        "add" => Ok(BlendMode::Add),
+        "multiply" => Ok(BlendMode::Multiply),
+        "screen" => Ok(BlendMode::Screen),
+        "xor" => Ok(BlendMode::Xor),
+        _ => Err(Error::new(ErrorKind::Other, "Unknown blend mode")),
+    }
+}
+
+//combines the color of a cell with a new color, alpha 0 keeps the cell, 255 applies the mode fully
+pub fn blend(below: RGB, above: RGB, alpha: u8, mode: &BlendMode) -> RGB {
+    let channel = |a: u8, b: u8| {
+        let mixed = match mode {
+            BlendMode::Normal => b as u32,
+            BlendMode::Add => (a as u32 + b as u32).min(255),
+            BlendMode::Multiply => a as u32 * b as u32 / 255,
+            BlendMode::Screen => 255 - (255 - a as u32) * (255 - b as u32) / 255,
+            BlendMode::Xor => (a ^ b) as u32,
+        };
+        ((a as u32 * (255 - alpha as u32) + mixed * alpha as u32) / 255) as u8
+    };
+
+    RGB {
+        red: channel(below.red, above.red),
+        green: channel(below.green, above.green),
+        blue: channel(below.blue, above.blue),
+    }
+}
+
+//writes a color into a cell of the grid, the position has to be in range
+pub fn paint(grid: &mut Grid, row: usize, column: usize, color: RGB, alpha: u8, mode: &BlendMode) {
+    let below = grid.grid[row][column];
+    grid.grid[row][column] = blend(below, color, alpha, mode);
+    grid.opaque[row][column] = true;
+}
@@ -0,0 +1,439 @@
+use std::io::{Error, ErrorKind};
+
+use crate::lib::api::Color;
+use crate::lib::data::RGB;
+use crate::lib::{err, palette};
+
+//converts any of the accepted color formats into a color and its alpha value,
+//palette indices are looked up in the given palette colors
+pub fn resolve(color: &Color, palette: &[RGB]) -> std::io::Result<(RGB, u8)> {
+    resolve_color(color, palette)
+        .map_err(|error| err::rejection("invalid_color", error.to_string()))
+}
+
+fn resolve_color(color: &Color, palette: &[RGB]) -> std::io::Result<(RGB, u8)> {
+    match color {
+        Color::Index(index) => Ok((
+            palette::color_at(palette, index.index)?,
+            index.alpha.unwrap_or(255),
+        )),
+        Color::Indexed(index) => Ok((palette::color_at(palette, *index)?, 255)),
+        Color::Rgb(rgb) => Ok((
+            RGB {
+                red: rgb.red,
+                green: rgb.green,
+                blue: rgb.blue,
+            },
+            rgb.alpha.unwrap_or(255),
+        )),
+        Color::Hsv(hsv) => {
+            check_hsx(hsv.h, hsv.s, hsv.v, "Value")?;
+            Ok((hsv_to_rgb(hsv.h, hsv.s, hsv.v), hsv.alpha.unwrap_or(255)))
+        }
+        Color::Hsl(hsl) => {
+            check_hsx(hsl.h, hsl.s, hsl.l, "Lightness")?;
+            Ok((hsl_to_rgb(hsl.h, hsl.s, hsl.l), hsl.alpha.unwrap_or(255)))
+        }
+        Color::Named(named) => {
+            let (rgb, alpha) = parse_color(&named.color)?;
+            Ok((rgb, named.alpha.unwrap_or(alpha)))
+        }
+        Color::Text(text) => parse_color(text),
+    }
+}
+
+//for colors that fill the grid, where alpha is not used
+pub fn resolve_optional(color: &Option<Color>, palette: &[RGB]) -> std::io::Result<Option<RGB>> {
+    match color {
+        Some(color) => Ok(Some(resolve(color, palette)?.0)),
+        None => Ok(None),
+    }
+}
+
+pub fn palette_index(color: &Color) -> Option<u8> {
+    match color {
+        Color::Index(index) => Some(index.index),
+        Color::Indexed(index) => Some(*index),
+        _ => None,
+    }
+}
+
+//"#rgb", "#rrggbb", "#rrggbbaa" or a css color name
+pub fn parse_color(text: &str) -> std::io::Result<(RGB, u8)> {
+    let text = text.trim();
+
+    if text.starts_with('#') {
+        return parse_hex(&text[1..]);
+    }
+
+    let name = text.to_lowercase();
+    match CSS_COLORS.binary_search_by(|(css_name, _)| css_name.cmp(&name.as_str())) {
+        Ok(index) => Ok((from_u32(CSS_COLORS[index].1), 255)),
+        Err(_) => Err(Error::new(
+            ErrorKind::Other,
+            format!("Unknown color name: {}", text),
+        )),
+    }
+}
+
+fn parse_hex(digits: &str) -> std::io::Result<(RGB, u8)> {
+    let invalid = || {
+        Error::new(
+            ErrorKind::Other,
+            "Hex colors must look like #rgb, #rrggbb or #rrggbbaa",
+        )
+    };
+    //from_str_radix would also take a sign
+    if !digits.chars().all(|digit| digit.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let value = u32::from_str_radix(digits, 16).map_err(|_| invalid())?;
+
+    match digits.len() {
+        3 => {
+            let expand = |nibble: u32| (nibble * 17) as u8;
+            Ok((
+                RGB {
+                    red: expand(value >> 8 & 0xF),
+                    green: expand(value >> 4 & 0xF),
+                    blue: expand(value & 0xF),
+                },
+                255,
+            ))
+        }
+        6 => Ok((from_u32(value), 255)),
+        8 => Ok((from_u32(value >> 8), (value & 0xFF) as u8)),
+        _ => Err(invalid()),
+    }
+}
+
+pub fn from_u32(value: u32) -> RGB {
+    RGB {
+        red: (value >> 16 & 0xFF) as u8,
+        green: (value >> 8 & 0xFF) as u8,
+        blue: (value & 0xFF) as u8,
+    }
+}
+
+fn check_hsx(hue: f64, saturation: f64, third: f64, third_name: &str) -> std::io::Result<()> {
+    if !(0.0..=360.0).contains(&hue) {
+        Err(Error::new(
+            ErrorKind::Other,
+            "Hue must be between 0 and 360",
+        ))
+    } else if !(0.0..=1.0).contains(&saturation) {
+        Err(Error::new(
+            ErrorKind::Other,
+            "Saturation must be between 0 and 1",
+        ))
+    } else if !(0.0..=1.0).contains(&third) {
+        Err(Error::new(
+            ErrorKind::Other,
+            format!("{} must be between 0 and 1", third_name),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+//hue in degrees, saturation and value from 0.0 to 1.0
+pub fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> RGB {
+    let chroma = value * saturation;
+    from_chroma(hue, chroma, value - chroma)
+}
+
+//hue in degrees, saturation and lightness from 0.0 to 1.0
+pub fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> RGB {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    from_chroma(hue, chroma, lightness - chroma / 2.0)
+}
+
+fn from_chroma(hue: f64, chroma: f64, m: f64) -> RGB {
+    let hue = ((hue % 360.0) + 360.0) % 360.0;
+    let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+
+    RGB {
+        red: ((r + m) * 255.0).round() as u8,
+        green: ((g + m) * 255.0).round() as u8,
+        blue: ((b + m) * 255.0).round() as u8,
+    }
+}
+
+//sorted by name for binary search
+const CSS_COLORS: [(&str, u32); 148] = [
+    ("aliceblue", 0xf0f8ff),
+    ("antiquewhite", 0xfaebd7),
+    ("aqua", 0x00ffff),
+    ("aquamarine", 0x7fffd4),
+    ("azure", 0xf0ffff),
+    ("beige", 0xf5f5dc),
+    ("bisque", 0xffe4c4),
+    ("black", 0x000000),
+    ("blanchedalmond", 0xffebcd),
+    ("blue", 0x0000ff),
+    ("blueviolet", 0x8a2be2),
+    ("brown", 0xa52a2a),
+    ("burlywood", 0xdeb887),
+    ("cadetblue", 0x5f9ea0),
+    ("chartreuse", 0x7fff00),
+    ("chocolate", 0xd2691e),
+    ("coral", 0xff7f50),
+    ("cornflowerblue", 0x6495ed),
+    ("cornsilk", 0xfff8dc),
+    ("crimson", 0xdc143c),
+    ("cyan", 0x00ffff),
+    ("darkblue", 0x00008b),
+    ("darkcyan", 0x008b8b),
+    ("darkgoldenrod", 0xb8860b),
+    ("darkgray", 0xa9a9a9),
+    ("darkgreen", 0x006400),
+    ("darkgrey", 0xa9a9a9),
+    ("darkkhaki", 0xbdb76b),
+    ("darkmagenta", 0x8b008b),
+    ("darkolivegreen", 0x556b2f),
+    ("darkorange", 0xff8c00),
+    ("darkorchid", 0x9932cc),
+    ("darkred", 0x8b0000),
+    ("darksalmon", 0xe9967a),
+    ("darkseagreen", 0x8fbc8f),
+    ("darkslateblue", 0x483d8b),
+    ("darkslategray", 0x2f4f4f),
+    ("darkslategrey", 0x2f4f4f),
+    ("darkturquoise", 0x00ced1),
+    ("darkviolet", 0x9400d3),
+    ("deeppink", 0xff1493),
+    ("deepskyblue", 0x00bfff),
+    ("dimgray", 0x696969),
+    ("dimgrey", 0x696969),
+    ("dodgerblue", 0x1e90ff),
+    ("firebrick", 0xb22222),
+    ("floralwhite", 0xfffaf0),
+    ("forestgreen", 0x228b22),
+    ("fuchsia", 0xff00ff),
+    ("gainsboro", 0xdcdcdc),
+    ("ghostwhite", 0xf8f8ff),
+    ("gold", 0xffd700),
+    ("goldenrod", 0xdaa520),
+    ("gray", 0x808080),
+    ("green", 0x008000),
+    ("greenyellow", 0xadff2f),
+    ("grey", 0x808080),
+    ("honeydew", 0xf0fff0),
+    ("hotpink", 0xff69b4),
+    ("indianred", 0xcd5c5c),
+    ("indigo", 0x4b0082),
+    ("ivory", 0xfffff0),
+    ("khaki", 0xf0e68c),
+    ("lavender", 0xe6e6fa),
+    ("lavenderblush", 0xfff0f5),
+    ("lawngreen", 0x7cfc00),
+    ("lemonchiffon", 0xfffacd),
+    ("lightblue", 0xadd8e6),
+    ("lightcoral", 0xf08080),
+    ("lightcyan", 0xe0ffff),
+    ("lightgoldenrodyellow", 0xfafad2),
+    ("lightgray", 0xd3d3d3),
+    ("lightgreen", 0x90ee90),
+    ("lightgrey", 0xd3d3d3),
+    ("lightpink", 0xffb6c1),
+    ("lightsalmon", 0xffa07a),
+    ("lightseagreen", 0x20b2aa),
+    ("lightskyblue", 0x87cefa),
+    ("lightslategray", 0x778899),
+    ("lightslategrey", 0x778899),
+    ("lightsteelblue", 0xb0c4de),
+    ("lightyellow", 0xffffe0),
+    ("lime", 0x00ff00),
+    ("limegreen", 0x32cd32),
+    ("linen", 0xfaf0e6),
+    ("magenta", 0xff00ff),
+    ("maroon", 0x800000),
+    ("mediumaquamarine", 0x66cdaa),
+    ("mediumblue", 0x0000cd),
+    ("mediumorchid", 0xba55d3),
+    ("mediumpurple", 0x9370db),
+    ("mediumseagreen", 0x3cb371),
+    ("mediumslateblue", 0x7b68ee),
+    ("mediumspringgreen", 0x00fa9a),
+    ("mediumturquoise", 0x48d1cc),
+    ("mediumvioletred", 0xc71585),
+    ("midnightblue", 0x191970),
+    ("mintcream", 0xf5fffa),
+    ("mistyrose", 0xffe4e1),
+    ("moccasin", 0xffe4b5),
+    ("navajowhite", 0xffdead),
+    ("navy", 0x000080),
+    ("oldlace", 0xfdf5e6),
+    ("olive", 0x808000),
+    ("olivedrab", 0x6b8e23),
+    ("orange", 0xffa500),
+    ("orangered", 0xff4500),
+    ("orchid", 0xda70d6),
+    ("palegoldenrod", 0xeee8aa),
+    ("palegreen", 0x98fb98),
+    ("paleturquoise", 0xafeeee),
+    ("palevioletred", 0xdb7093),
+    ("papayawhip", 0xffefd5),
+    ("peachpuff", 0xffdab9),
+    ("peru", 0xcd853f),
+    ("pink", 0xffc0cb),
+    ("plum", 0xdda0dd),
+    ("powderblue", 0xb0e0e6),
+    ("purple", 0x800080),
+    ("rebeccapurple", 0x663399),
+    ("red", 0xff0000),
+    ("rosybrown", 0xbc8f8f),
+    ("royalblue", 0x4169e1),
+    ("saddlebrown", 0x8b4513),
+    ("salmon", 0xfa8072),
+    ("sandybrown", 0xf4a460),
+    ("seagreen", 0x2e8b57),
+    ("seashell", 0xfff5ee),
+    ("sienna", 0xa0522d),
+    ("silver", 0xc0c0c0),
+    ("skyblue", 0x87ceeb),
+    ("slateblue", 0x6a5acd),
+    ("slategray", 0x708090),
+    ("slategrey", 0x708090),
+    ("snow", 0xfffafa),
+    ("springgreen", 0x00ff7f),
+    ("steelblue", 0x4682b4),
+    ("tan", 0xd2b48c),
+    ("teal", 0x008080),
+    ("thistle", 0xd8bfd8),
+    ("tomato", 0xff6347),
+    ("turquoise", 0x40e0d0),
+    ("violet", 0xee82ee),
+    ("wheat", 0xf5deb3),
+    ("white", 0xffffff),
+    ("whitesmoke", 0xf5f5f5),
+    ("yellow", 0xffff00),
+    ("yellowgreen", 0x9acd32),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib::api::{NamedColor, PaletteIndex};
+
+    fn rgb(red: u8, green: u8, blue: u8) -> RGB {
+        RGB { red, green, blue }
+    }
+
+    #[test]
+    fn hex_colors_have_3_6_or_8_digits() {
+        assert_eq!(parse_color("#f80").unwrap(), (rgb(0xFF, 0x88, 0x00), 255));
+        assert_eq!(
+            parse_color("#ff8000").unwrap(),
+            (rgb(0xFF, 0x80, 0x00), 255)
+        );
+        assert_eq!(
+            parse_color("#ff800080").unwrap(),
+            (rgb(0xFF, 0x80, 0x00), 0x80)
+        );
+        assert_eq!(
+            parse_color(" #FF8000 ").unwrap(),
+            (rgb(0xFF, 0x80, 0x00), 255)
+        );
+    }
+
+    #[test]
+    fn malformed_hex_colors_are_rejected() {
+        for text in [
+            "#",
+            "#f",
+            "#ff80",
+            "#ff8000f",
+            "#ff8000800",
+            "#gg8000",
+            "#+f8",
+            "#-f8",
+            "#ff 000",
+        ]
+        .iter()
+        {
+            assert!(parse_color(text).is_err(), "{} was accepted", text);
+        }
+    }
+
+    #[test]
+    fn css_names_ignore_case() {
+        assert_eq!(
+            parse_color("hotpink").unwrap(),
+            (rgb(0xFF, 0x69, 0xB4), 255)
+        );
+        assert_eq!(
+            parse_color("RebeccaPurple").unwrap(),
+            (rgb(0x66, 0x33, 0x99), 255)
+        );
+        assert_eq!(parse_color("aliceblue").unwrap().0, rgb(0xF0, 0xF8, 0xFF));
+        assert_eq!(parse_color("yellowgreen").unwrap().0, rgb(0x9A, 0xCD, 0x32));
+    }
+
+    #[test]
+    fn unknown_css_names_are_rejected() {
+        let error = parse_color("hotpinkish").unwrap_err();
+        assert_eq!(error.to_string(), "Unknown color name: hotpinkish");
+        assert!(parse_color("").is_err());
+        assert!(parse_color("ff8000").is_err());
+    }
+
+    //the names are looked up with a binary search
+    #[test]
+    fn css_names_are_sorted() {
+        assert!(CSS_COLORS.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        for (name, value) in CSS_COLORS.iter() {
+            assert_eq!(parse_color(name).unwrap(), (from_u32(*value), 255));
+        }
+    }
+
+    #[test]
+    fn named_colors_keep_their_alpha() {
+        let named = Color::Named(NamedColor {
+            color: String::from("#ff800080"),
+            alpha: None,
+        });
+        assert_eq!(resolve(&named, &[]).unwrap().1, 0x80);
+
+        let named = Color::Named(NamedColor {
+            color: String::from("red"),
+            alpha: Some(10),
+        });
+        assert_eq!(resolve(&named, &[]).unwrap(), (rgb(0xFF, 0, 0), 10));
+    }
+
+    #[test]
+    fn indices_are_looked_up_in_the_palette() {
+        let palette = [rgb(1, 2, 3), rgb(4, 5, 6)];
+        assert_eq!(
+            resolve(&Color::Indexed(1), &palette).unwrap(),
+            (rgb(4, 5, 6), 255)
+        );
+
+        let index = Color::Index(PaletteIndex {
+            index: 0,
+            alpha: Some(10),
+        });
+        assert_eq!(resolve(&index, &palette).unwrap(), (rgb(1, 2, 3), 10));
+
+        let error = resolve(&Color::Indexed(2), &palette).unwrap_err();
+        assert_eq!(error.to_string(), "Palette index out of range");
+    }
+
+    #[test]
+    fn invalid_colors_are_rejected_as_invalid_color() {
+        let error = resolve(&Color::Text(String::from("#ff80")), &[]).unwrap_err();
+        assert_eq!(err::reason(&error), "invalid_color");
+        let error = resolve(&Color::Indexed(3), &[]).unwrap_err();
+        assert_eq!(err::reason(&error), "invalid_color");
+    }
+}
@@ -2,18 +2,41 @@ use sdl2::render::Canvas;
 use sdl2::video::Window;
 use std::sync::{Arc, Mutex};
 
+#[derive(Clone)]
 pub struct Grid {
     pub grid: Vec<Vec<RGB>>,
+    pub background: RGB,
+    //palette index a cell was drawn with, if any
+    pub indices: Vec<Vec<Option<u8>>>,
+    //cells that were not drawn since the layer was erased let the layers below shine through.
+    //The bottom layer is always opaque.
+    pub opaque: Vec<Vec<bool>>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub struct RGB {
     pub red: u8,
     pub green: u8,
     pub blue: u8,
 }
 
+pub struct Layer {
+    pub name: String,
+    pub grid: Grid,
+    pub z_index: i32,
+    pub visible: bool,
+    pub opacity: f32,
+}
+
+//ordered from bottom to top
+pub struct Layers {
+    pub layers: Vec<Layer>,
+    //shown above all layers while an intervention is active, nothing of the layers shines through
+    pub intervention: Option<Grid>,
+}
+
 pub struct SharedGrid {
-    pub sharedgrid: Arc<Mutex<Grid>>,
+    pub sharedgrid: Arc<Mutex<Layers>>,
 }
 
 pub struct ScreenResolution {
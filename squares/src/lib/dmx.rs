@@ -0,0 +1,415 @@
+use std::convert::TryFrom;
+use std::io::{Error, ErrorKind};
+use std::net::{Ipv4Addr, UdpSocket};
+
+use crate::lib::blend::{self, BlendMode};
+use crate::lib::data::{SharedGrid, RGB};
+use crate::lib::{layers, palette};
+
+pub const ARTNET_PORT: u16 = 6454;
+pub const E131_PORT: u16 = 5568;
+
+//a dmx universe has 512 channels, 170 cells with 3 channels each
+pub const CELLS_PER_UNIVERSE: usize = 170;
+
+const ARTNET_ID: &[u8] = b"Art-Net\0";
+const ARTNET_OP_DMX: u16 = 0x5000;
+const E131_ID: &[u8] = b"ASC-E1.17\0\0\0";
+//the options bit of sACN preview data, which is not meant to be shown
+const E131_PREVIEW: u8 = 0x80;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Layout {
+    //every row starts at column 0
+    RowMajor,
+    //every second row runs backwards, like LED strips that are laid out in a zigzag
+    Serpentine,
+}
+
+//which cells the channels of every universe are written to
+#[derive(Clone)]
+pub struct Mapping {
+    pub layout: Layout,
+    pub rows: usize,
+    pub columns: usize,
+    pub first_universe: u16,
+    pub cells_per_universe: usize,
+    //the cell the first channel of a universe goes to, for universes that don't follow in order
+    pub offsets: Vec<(u16, usize)>,
+}
+
+pub fn layout_from_name(name: &str) -> std::io::Result<Layout> {
+    match name {
+        "row-major" => Ok(Layout::RowMajor),
+        "serpentine" => Ok(Layout::Serpentine),
+        _ => Err(Error::new(ErrorKind::Other, "Unknown layout")),
+    }
+}
+
+//"<universe>:<cell>", the cell is counted from the first cell of the grid along the layout
+pub fn parse_offset(input: &str) -> std::io::Result<(u16, usize)> {
+    let values: Vec<&str> = input.split(':').collect();
+    if values.len() == 2 {
+        if let (Ok(universe), Ok(cell)) = (values[0].parse(), values[1].parse()) {
+            return Ok((universe, cell));
+        }
+    }
+    Err(Error::new(
+        ErrorKind::Other,
+        "Offsets are written as <universe>:<cell>",
+    ))
+}
+
+//the number of the first cell of a universe, None if the universe is not mapped
+fn universe_offset(mapping: &Mapping, universe: u16) -> Option<usize> {
+    if let Some((_, cell)) = mapping.offsets.iter().find(|(u, _)| *u == universe) {
+        return Some(*cell);
+    }
+    if universe < mapping.first_universe {
+        return None;
+    }
+    Some((universe - mapping.first_universe) as usize * mapping.cells_per_universe)
+}
+
+//row and column of the nth cell along the layout
+fn cell_position(mapping: &Mapping, cell: usize) -> (usize, usize) {
+    let row = cell / mapping.columns;
+    let column = cell % mapping.columns;
+
+    match mapping.layout {
+        Layout::Serpentine if row % 2 == 1 => (row, mapping.columns - 1 - column),
+        _ => (row, column),
+    }
+}
+
+//the universes that are needed to cover the whole grid, sACN is sent to one multicast group each
+fn universes(mapping: &Mapping) -> std::io::Result<Vec<u16>> {
+    let cells = mapping.rows * mapping.columns;
+    let count = (cells + mapping.cells_per_universe - 1) / mapping.cells_per_universe;
+
+    let universes: Option<Vec<u16>> = (0..count)
+        .map(|i| mapping.first_universe.checked_add(u16::try_from(i).ok()?))
+        .collect();
+    let mut universes = match universes {
+        Some(universes) => universes,
+        None => {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "The grid needs universes past 65535, start at a lower universe",
+            ))
+        }
+    };
+    for (universe, _) in mapping.offsets.iter() {
+        if !universes.contains(universe) {
+            universes.push(*universe);
+        }
+    }
+    Ok(universes)
+}
+
+//fails if the grid does not fit into the universes after the first one
+pub fn check_mapping(mapping: &Mapping) -> std::io::Result<()> {
+    universes(mapping).map(|_| ())
+}
+
+//universe and dmx data of an ArtDmx packet
+pub fn parse_artnet(packet: &[u8]) -> std::io::Result<(u16, &[u8])> {
+    if packet.len() < 18 || &packet[0..8] != ARTNET_ID {
+        return Err(Error::new(ErrorKind::Other, "Not an Art-Net packet"));
+    }
+    //the opcode and the universe are little-endian, the length is big-endian
+    let opcode = u16::from(packet[8]) | u16::from(packet[9]) << 8;
+    if opcode != ARTNET_OP_DMX {
+        return Err(Error::new(ErrorKind::Other, "Not an ArtDmx packet"));
+    }
+
+    let universe = u16::from(packet[14]) | u16::from(packet[15]) << 8;
+    let length = (u16::from(packet[16]) << 8 | u16::from(packet[17])) as usize;
+    match packet.get(18..18 + length) {
+        Some(data) => Ok((universe, data)),
+        None => Err(Error::new(ErrorKind::Other, "Art-Net packet is too short")),
+    }
+}
+
+//universe and dmx data of an E1.31 data packet, without the start code
+pub fn parse_e131(packet: &[u8]) -> std::io::Result<(u16, &[u8])> {
+    if packet.len() < 126 || &packet[4..16] != E131_ID {
+        return Err(Error::new(ErrorKind::Other, "Not an E1.31 packet"));
+    }
+    if packet[112] & E131_PREVIEW != 0 {
+        return Err(Error::new(ErrorKind::Other, "E1.31 preview data is not shown"));
+    }
+    //only dmx data with the null start code contains colors
+    if packet[125] != 0 {
+        return Err(Error::new(ErrorKind::Other, "Not an E1.31 dmx packet"));
+    }
+
+    let universe = u16::from(packet[113]) << 8 | u16::from(packet[114]);
+    //the count includes the start code
+    let count = (u16::from(packet[123]) << 8 | u16::from(packet[124])) as usize;
+    match packet.get(126..125 + count.max(1)) {
+        Some(data) => Ok((universe, data)),
+        None => Err(Error::new(ErrorKind::Other, "E1.31 packet is too short")),
+    }
+}
+
+//writes the channels of a universe to the content layer, three channels per cell
+pub fn apply_universe(
+    mapping: &Mapping,
+    universe: u16,
+    data: &[u8],
+    sharedgrid: &SharedGrid,
+) -> std::io::Result<()> {
+    let offset = match universe_offset(mapping, universe) {
+        Some(offset) => offset,
+        None => return Err(Error::new(ErrorKind::Other, "Universe is not mapped")),
+    };
+
+    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+    let grid_data = layers::layer_mut(&mut sharedgrid_data, &None)?;
+
+    for (i, channels) in data.chunks(3).enumerate() {
+        if i >= mapping.cells_per_universe || channels.len() < 3 {
+            break;
+        }
+        let (row, column) = cell_position(mapping, offset + i);
+        if row >= grid_data.grid.len() || column >= grid_data.grid[0].len() {
+            break;
+        }
+
+        let color = RGB {
+            red: channels[0],
+            green: channels[1],
+            blue: channels[2],
+        };
+        blend::paint(grid_data, row, column, color, 255, &BlendMode::Normal);
+        palette::mark(grid_data, row, column, None);
+    }
+    Ok(())
+}
+
+pub fn listen_artnet(mapping: Mapping, sharedgrid: SharedGrid) -> std::io::Result<()> {
+    let socket = UdpSocket::bind(("0.0.0.0", ARTNET_PORT))?;
+    println!("Art-Net receiver on port {}", ARTNET_PORT);
+    receive(&socket, parse_artnet, &mapping, &sharedgrid)
+}
+
+//sACN is usually sent to a multicast group per universe, unicast packets are received as well
+pub fn listen_e131(mapping: Mapping, sharedgrid: SharedGrid) -> std::io::Result<()> {
+    let socket = UdpSocket::bind(("0.0.0.0", E131_PORT))?;
+    for universe in universes(&mapping)? {
+        let group = Ipv4Addr::new(239, 255, (universe >> 8) as u8, universe as u8);
+        if let Err(error) = socket.join_multicast_v4(&group, &Ipv4Addr::UNSPECIFIED) {
+            println!("Could not join multicast group of universe {}: {}", universe, error);
+        }
+    }
+    println!("E1.31 receiver on port {}", E131_PORT);
+    receive(&socket, parse_e131, &mapping, &sharedgrid)
+}
+
+fn receive(
+    socket: &UdpSocket,
+    parse: fn(&[u8]) -> std::io::Result<(u16, &[u8])>,
+    mapping: &Mapping,
+    sharedgrid: &SharedGrid,
+) -> std::io::Result<()> {
+    let mut buffer = vec![0; 1024];
+    loop {
+        let (length, _source) = socket.recv_from(&mut buffer)?;
+
+        //packets that are not dmx data or belong to other universes are dropped
+        if let Ok((universe, data)) = parse(&buffer[..length]) {
+            let _ = apply_universe(mapping, universe, data, sharedgrid);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib;
+
+    const BLACK: RGB = RGB {
+        red: 0,
+        green: 0,
+        blue: 0,
+    };
+
+    fn mapping(layout: Layout, rows: usize, columns: usize, first_universe: u16) -> Mapping {
+        Mapping {
+            layout,
+            rows,
+            columns,
+            first_universe,
+            cells_per_universe: CELLS_PER_UNIVERSE,
+            offsets: Vec::new(),
+        }
+    }
+
+    fn artnet(opcode: u16, universe: u16, length: u16, data: &[u8]) -> Vec<u8> {
+        let mut packet = ARTNET_ID.to_vec();
+        packet.extend_from_slice(&[opcode as u8, (opcode >> 8) as u8]);
+        //protocol version, sequence and physical port
+        packet.extend_from_slice(&[0, 14, 0, 0]);
+        packet.extend_from_slice(&[universe as u8, (universe >> 8) as u8]);
+        packet.extend_from_slice(&[(length >> 8) as u8, length as u8]);
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    fn e131(universe: u16, options: u8, start_code: u8, count: u16, data: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0; 126];
+        packet[4..16].copy_from_slice(E131_ID);
+        packet[112] = options;
+        packet[113] = (universe >> 8) as u8;
+        packet[114] = universe as u8;
+        packet[123] = (count >> 8) as u8;
+        packet[124] = count as u8;
+        packet[125] = start_code;
+        packet.extend_from_slice(data);
+        packet
+    }
+
+    #[test]
+    fn artnet_universe_is_little_endian() {
+        let packet = artnet(ARTNET_OP_DMX, 0x0102, 3, &[1, 2, 3]);
+        let (universe, data) = parse_artnet(&packet).unwrap();
+        assert_eq!(universe, 0x0102);
+        assert_eq!(data, &[1, 2, 3]);
+    }
+
+    #[test]
+    fn artnet_data_ends_at_the_length() {
+        let packet = artnet(ARTNET_OP_DMX, 0, 3, &[1, 2, 3, 4, 5, 6]);
+        assert_eq!(parse_artnet(&packet).unwrap().1, &[1, 2, 3]);
+    }
+
+    #[test]
+    fn artnet_rejects_malformed_packets() {
+        let mut wrong_id = artnet(ARTNET_OP_DMX, 0, 3, &[1, 2, 3]);
+        wrong_id[0] = b'a';
+        assert!(parse_artnet(&wrong_id).is_err());
+
+        //ArtPoll
+        assert!(parse_artnet(&artnet(0x2000, 0, 0, &[])).is_err());
+
+        let header = artnet(ARTNET_OP_DMX, 0, 0, &[]);
+        assert!(parse_artnet(&header[..17]).is_err());
+        assert!(parse_artnet(&[]).is_err());
+    }
+
+    #[test]
+    fn artnet_rejects_data_shorter_than_the_length() {
+        let packet = artnet(ARTNET_OP_DMX, 0, 512, &[1, 2, 3]);
+        assert!(parse_artnet(&packet).is_err());
+    }
+
+    #[test]
+    fn e131_universe_is_big_endian() {
+        let packet = e131(0x0102, 0, 0, 4, &[1, 2, 3]);
+        let (universe, data) = parse_e131(&packet).unwrap();
+        assert_eq!(universe, 0x0102);
+        assert_eq!(data, &[1, 2, 3]);
+    }
+
+    #[test]
+    fn e131_count_includes_the_start_code() {
+        let packet = e131(1, 0, 0, 3, &[1, 2, 3, 4]);
+        assert_eq!(parse_e131(&packet).unwrap().1, &[1, 2]);
+
+        //no channels at all
+        let packet = e131(1, 0, 0, 0, &[]);
+        assert_eq!(parse_e131(&packet).unwrap().1, &[] as &[u8]);
+    }
+
+    #[test]
+    fn e131_rejects_malformed_packets() {
+        let mut wrong_id = e131(1, 0, 0, 4, &[1, 2, 3]);
+        wrong_id[4] = b'B';
+        assert!(parse_e131(&wrong_id).is_err());
+
+        assert!(parse_e131(&e131(1, E131_PREVIEW, 0, 4, &[1, 2, 3])).is_err());
+        //a start code other than 0 is not dmx data, e.g. 0xDD for per channel priorities
+        assert!(parse_e131(&e131(1, 0, 0xDD, 4, &[1, 2, 3])).is_err());
+
+        let header = e131(1, 0, 0, 1, &[]);
+        assert!(parse_e131(&header[..125]).is_err());
+        assert!(parse_e131(&e131(1, 0, 0, 513, &[1, 2, 3])).is_err());
+    }
+
+    #[test]
+    fn offsets_are_universe_and_cell() {
+        assert_eq!(parse_offset("3:170").unwrap(), (3, 170));
+        assert!(parse_offset("3").is_err());
+        assert!(parse_offset("3:170:1").is_err());
+        assert!(parse_offset("a:170").is_err());
+        assert!(parse_offset("70000:1").is_err());
+    }
+
+    #[test]
+    fn universes_follow_the_first_one() {
+        let mut mapping = mapping(Layout::RowMajor, 16, 32, 1);
+        assert_eq!(universe_offset(&mapping, 0), None);
+        assert_eq!(universe_offset(&mapping, 1), Some(0));
+        assert_eq!(universe_offset(&mapping, 3), Some(2 * CELLS_PER_UNIVERSE));
+
+        mapping.offsets.push((3, 100));
+        assert_eq!(universe_offset(&mapping, 3), Some(100));
+    }
+
+    #[test]
+    fn universes_cover_the_grid() {
+        let mut mapping = mapping(Layout::RowMajor, 16, 32, 1);
+        mapping.offsets = vec![(2, 0), (9, 0)];
+        //512 cells need 4 universes
+        assert_eq!(universes(&mapping).unwrap(), vec![1, 2, 3, 4, 9]);
+    }
+
+    #[test]
+    fn universes_past_65535_are_rejected() {
+        let mapping = mapping(Layout::RowMajor, 1, 170, 65535);
+        assert_eq!(universes(&mapping).unwrap(), vec![65535]);
+        assert!(check_mapping(&mapping).is_ok());
+
+        let mapping = Mapping {
+            columns: 171,
+            ..mapping
+        };
+        assert!(universes(&mapping).is_err());
+        assert!(check_mapping(&mapping).is_err());
+    }
+
+    #[test]
+    fn serpentine_rows_run_backwards() {
+        let row_major = mapping(Layout::RowMajor, 2, 3, 0);
+        assert_eq!(cell_position(&row_major, 4), (1, 1));
+        assert_eq!(cell_position(&row_major, 3), (1, 0));
+
+        let serpentine = mapping(Layout::Serpentine, 2, 3, 0);
+        assert_eq!(cell_position(&serpentine, 2), (0, 2));
+        assert_eq!(cell_position(&serpentine, 3), (1, 2));
+        assert_eq!(cell_position(&serpentine, 5), (1, 0));
+    }
+
+    #[test]
+    fn channels_are_written_to_the_content_layer() {
+        let sharedgrid = lib::grid_init(2, 2, BLACK);
+        let mapping = mapping(Layout::Serpentine, 2, 2, 0);
+        //the last channel does not make up a whole cell
+        let data = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+        apply_universe(&mapping, 0, &data, &sharedgrid).unwrap();
+
+        let mut layers = sharedgrid.sharedgrid.lock().unwrap();
+        let grid = &layers::layer_mut(&mut layers, &None).unwrap().grid;
+        let cell = |red, green, blue| RGB { red, green, blue };
+        assert_eq!(grid[0], vec![cell(1, 2, 3), cell(4, 5, 6)]);
+        assert_eq!(grid[1], vec![BLACK, cell(7, 8, 9)]);
+    }
+
+    #[test]
+    fn unmapped_universes_are_rejected() {
+        let sharedgrid = lib::grid_init(2, 2, BLACK);
+        let mapping = mapping(Layout::RowMajor, 2, 2, 1);
+        assert!(apply_universe(&mapping, 0, &[1, 2, 3], &sharedgrid).is_err());
+    }
+}
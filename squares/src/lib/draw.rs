@@ -0,0 +1,293 @@
+use crate::lib::api::{ApiGrid, ApiPattern, Cell, Clear, Line};
+use crate::lib::blend::BlendMode;
+use crate::lib::data::{Grid, SharedGrid, RGB};
+use crate::lib::pattern::{Pattern, PatternParams};
+use crate::lib::regions::{self, Area};
+use crate::lib::{blend, color, err, layers, palette, pattern, wire};
+
+//drawing shared by the http requests, mqtt, the schedule and the intervention, palette indices
+//are looked up in colors. Only the cells inside the area are drawn.
+
+pub fn cell(
+    cell: &Cell,
+    colors: &[RGB],
+    area: &Area,
+    sharedgrid: &SharedGrid,
+) -> std::io::Result<()> {
+    let mode = blend::blend_mode_from_name(&cell.blend)?;
+    let (color_arr, alpha) = color::resolve(&cell.color, colors)?;
+
+    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+    let grid_data = layers::layer_mut(&mut sharedgrid_data, &cell.layer)?;
+    let max_rows = &grid_data.grid.len();
+    let max_columns = &grid_data.grid[0].len();
+
+    //checks values
+    err::is_cell_value_in_range(cell, max_rows, max_columns)?;
+    let position = (cell.row, cell.column);
+    regions::check_cells(area, Some(position).into_iter(), *max_rows, *max_columns)?;
+    if !regions::contains(area, cell.row, cell.column) {
+        return Ok(());
+    }
+
+    let (row, column) = (cell.row as usize, cell.column as usize);
+    blend::paint(grid_data, row, column, color_arr, alpha, &mode);
+    let index = palette::exact_index(&cell.color, alpha, &mode);
+    palette::mark(grid_data, row, column, index);
+    Ok(())
+}
+
+pub fn line(
+    line: &Line,
+    colors: &[RGB],
+    area: &Area,
+    sharedgrid: &SharedGrid,
+) -> std::io::Result<()> {
+    let mode = blend::blend_mode_from_name(&line.blend)?;
+    let (color_arr, alpha) = color::resolve(&line.color, colors)?;
+
+    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+    let grid_data = layers::layer_mut(&mut sharedgrid_data, &line.layer)?;
+    let max_rows = &grid_data.grid.len();
+    let max_columns = &grid_data.grid[0].len();
+
+    //checks values
+    err::is_line_value_in_range(line, max_rows, max_columns)?;
+
+    let cells = line_cells(line, *max_rows, *max_columns);
+    regions::check_cells(area, cells.clone(), *max_rows, *max_columns)?;
+
+    let index = palette::exact_index(&line.color, alpha, &mode);
+    for (cell_row, cell_column) in cells {
+        if regions::contains(area, cell_row, cell_column) {
+            let (cell_row, cell_column) = (cell_row as usize, cell_column as usize);
+            blend::paint(grid_data, cell_row, cell_column, color_arr, alpha, &mode);
+            palette::mark(grid_data, cell_row, cell_column, index);
+        }
+    }
+    Ok(())
+}
+
+//the cells of a line that are on the grid, the rest is cut off. Start and length come from the
+//client, so they are added up as i64 and only the cells on the grid are visited.
+fn line_cells(
+    line: &Line,
+    max_rows: usize,
+    max_columns: usize,
+) -> impl Iterator<Item = (i32, i32)> + Clone {
+    let vertical = line.direction == 1;
+    let (start, across, along_max, across_max) = if vertical {
+        (line.row, line.column, max_rows, max_columns)
+    } else {
+        (line.column, line.row, max_columns, max_rows)
+    };
+
+    let first = i64::from(start).max(0);
+    let mut end = (i64::from(start) + i64::from(line.length)).min(along_max as i64);
+    if across < 0 || across as usize >= across_max {
+        end = first;
+    }
+
+    (first..end.max(first)).map(move |along| {
+        if vertical {
+            (along as i32, across)
+        } else {
+            (across, along as i32)
+        }
+    })
+}
+
+//how many cells a grid request draws at most, base64 has 4 characters for every 3 bytes
+pub fn grid_cells(grid: &ApiGrid) -> usize {
+    match &grid.data {
+        Some(data) => data.len() / 4,
+        None => 64,
+    }
+}
+
+//an 8x8 grid of colors, or rgb24 rows encoded as base64
+pub fn grid(
+    grid: &ApiGrid,
+    colors: &[RGB],
+    area: &Area,
+    sharedgrid: &SharedGrid,
+) -> std::io::Result<()> {
+    let mode = blend::blend_mode_from_name(&grid.blend)?;
+
+    if let Some(data) = &grid.data {
+        let bytes = wire::decode_base64(data)?;
+        let width = match grid.width {
+            Some(width) => width,
+            None => return Err(err::rejection("invalid_grid", "width is missing")),
+        };
+        let raw = wire::parse_base64(grid.zero_row, grid.zero_column, width, &bytes)?;
+
+        let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+        let grid_data = layers::layer_mut(&mut sharedgrid_data, &grid.layer)?;
+        return wire::write_rgb24(grid_data, &raw, &mode, area);
+    }
+
+    let api_grid = match &grid.api_grid {
+        Some(api_grid) => api_grid,
+        None => return Err(err::rejection("invalid_grid", "api_grid or data is missing")),
+    };
+    let resolved: std::io::Result<Vec<Vec<(RGB, u8)>>> = api_grid
+        .iter()
+        .map(|row| row.iter().map(|color| color::resolve(color, colors)).collect())
+        .collect();
+    let resolved = resolved?;
+
+    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+    let grid_data = layers::layer_mut(&mut sharedgrid_data, &grid.layer)?;
+    let max_rows = &grid_data.grid.len();
+    let max_columns = &grid_data.grid[0].len();
+
+    let cells = (0..8).flat_map(|i| (0..8).map(move |j| (i, j)));
+    let positions = cells.map(|(i, j)| {
+        (
+            grid.zero_row.saturating_add(i),
+            grid.zero_column.saturating_add(j),
+        )
+    });
+    regions::check_cells(area, positions, *max_rows, *max_columns)?;
+
+    for (i, row) in resolved.iter().enumerate() {
+        for (j, (color_arr, alpha)) in row.iter().enumerate() {
+            let cell_row = grid.zero_row.saturating_add(i as i32);
+            let cell_column = grid.zero_column.saturating_add(j as i32);
+
+            //cells that are out of range are not drawn
+            let values = err::is_position_in_range(cell_row, cell_column, max_rows, max_columns);
+            if values.is_ok() && regions::contains(area, cell_row, cell_column) {
+                let (cell_row, cell_column) = (cell_row as usize, cell_column as usize);
+                blend::paint(grid_data, cell_row, cell_column, *color_arr, *alpha, &mode);
+                let index = palette::exact_index(&api_grid[i][j], *alpha, &mode);
+                palette::mark(grid_data, cell_row, cell_column, index);
+            }
+        }
+    }
+    Ok(())
+}
+
+//the pattern of the request, values that are not given keep their defaults
+pub fn pattern_params(
+    api_pattern: &ApiPattern,
+    colors: &[RGB],
+    grid: &Grid,
+) -> std::io::Result<(Pattern, PatternParams)> {
+    let new_pattern = pattern::pattern_from_name(&api_pattern.name)?;
+    let mut params = pattern::default_params(grid);
+
+    if let Some(color) = color::resolve_optional(&api_pattern.color, colors)? {
+        params.color = color;
+    }
+    if let Some(color) = color::resolve_optional(&api_pattern.second_color, colors)? {
+        params.second_color = color;
+    }
+    if let Some(size) = api_pattern.size {
+        params.size = size;
+    }
+    if let Some(seed) = api_pattern.seed {
+        params.seed = seed;
+    }
+    Ok((new_pattern, params))
+}
+
+//a solid clear without a color erases the layer
+pub fn clear(clear: &Clear, colors: &[RGB], grid: &mut Grid) -> std::io::Result<()> {
+    let color = color::resolve_optional(&clear.color, colors)?;
+    let name = match &clear.pattern {
+        Some(name) => name.as_str(),
+        None => "solid",
+    };
+    let clear_pattern = pattern::pattern_from_name(name)?;
+
+    layers::clear_layer(grid, &clear_pattern, color);
+    Ok(())
+}
+
+//an image from image::load with one pixel per cell, from the top left corner. The file is
+//loaded before, so the grid is not locked while it is read.
+pub fn image(width: usize, height: usize, pixels: &[u8], grid: &mut Grid) -> std::io::Result<()> {
+    let raw = wire::RawGrid {
+        zero_row: 0,
+        zero_column: 0,
+        width,
+        height,
+        pixels,
+    };
+    wire::write_rgb24(grid, &raw, &BlendMode::Normal, &regions::everywhere())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib;
+    use crate::lib::api::Color;
+
+    const BLACK: RGB = RGB {
+        red: 0,
+        green: 0,
+        blue: 0,
+    };
+    const WHITE: RGB = RGB {
+        red: 255,
+        green: 255,
+        blue: 255,
+    };
+
+    fn new_line(row: i32, column: i32, direction: i32, length: i32) -> Line {
+        Line {
+            row,
+            column,
+            color: Color::Text(String::from("white")),
+            direction,
+            length,
+            blend: None,
+            layer: None,
+        }
+    }
+
+    fn cells(line: &Line) -> Vec<(i32, i32)> {
+        line_cells(line, 3, 4).collect()
+    }
+
+    #[test]
+    fn lines_are_cut_at_the_edges() {
+        assert_eq!(cells(&new_line(1, 2, 0, 5)), vec![(1, 2), (1, 3)]);
+        assert_eq!(cells(&new_line(-1, 0, 1, 3)), vec![(0, 0), (1, 0)]);
+        assert_eq!(cells(&new_line(0, -10, 0, 12)), vec![(0, 0), (0, 1)]);
+    }
+
+    #[test]
+    fn lines_off_the_grid_have_no_cells() {
+        assert!(cells(&new_line(3, 0, 0, 4)).is_empty());
+        assert!(cells(&new_line(-1, 0, 0, 4)).is_empty());
+        assert!(cells(&new_line(0, 4, 1, 3)).is_empty());
+        assert!(cells(&new_line(0, 0, 0, 0)).is_empty());
+        assert!(cells(&new_line(0, 0, 0, -5)).is_empty());
+    }
+
+    #[test]
+    fn long_lines_and_far_starts_do_not_overflow() {
+        let line = new_line(0, 1, 0, i32::max_value());
+        assert_eq!(cells(&line), vec![(0, 1), (0, 2), (0, 3)]);
+        let line = new_line(0, i32::max_value(), 0, i32::max_value());
+        assert!(cells(&line).is_empty());
+        let line = new_line(i32::min_value(), 0, 1, i32::max_value());
+        assert!(cells(&line).is_empty());
+    }
+
+    #[test]
+    fn lines_are_drawn_on_the_grid() {
+        let sharedgrid = lib::grid_init(4, 3, BLACK);
+        let line = new_line(2, 1, 0, 2_000_000_000);
+        let area = regions::everywhere();
+        super::line(&line, &[], &area, &sharedgrid).unwrap();
+
+        let mut layers = sharedgrid.sharedgrid.lock().unwrap();
+        let grid = &layers::layer_mut(&mut layers, &None).unwrap().grid;
+        assert_eq!(grid[2], vec![BLACK, WHITE, WHITE, WHITE]);
+        assert_eq!(grid[1], vec![BLACK; 4]);
+    }
+}
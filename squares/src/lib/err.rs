@@ -1,37 +1,96 @@
 extern crate rocket;
 
 use crate::lib::api::{Cell, Line};
-use rocket_contrib::json::Json;
+use std::fmt;
 use std::io::{Error, ErrorKind};
 
+//reasons of rejections that come from checking the values of a request, exported by /metrics
+pub const VALIDATION_CHECKS: [&str; 9] = [
+    "column_out_of_range",
+    "row_out_of_range",
+    "invalid_direction",
+    "invalid_color",
+    "invalid_opacity",
+    "invalid_grid",
+    "unknown_layer",
+    "outside_region",
+    "invalid_scale",
+];
+
 pub mod echain {
     error_chain! {}
 }
 
+//an error a request is rejected with. The message is what the client gets to read, the reason
+//is counted by /status and /metrics.
+#[derive(Debug)]
+pub struct Rejection {
+    pub reason: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for Rejection {}
+
+pub fn rejection<M: Into<String>>(reason: &'static str, message: M) -> Error {
+    Error::new(
+        ErrorKind::Other,
+        Rejection {
+            reason,
+            message: message.into(),
+        },
+    )
+}
+
+//errors that were not made with rejection() have no particular reason
+pub fn reason(error: &Error) -> &'static str {
+    match error
+        .get_ref()
+        .and_then(|inner| inner.downcast_ref::<Rejection>())
+    {
+        Some(rejection) => rejection.reason,
+        None => "invalid_request",
+    }
+}
+
 pub fn is_cell_value_in_range(
     cell: &Cell,
     max_rows: &usize,
     max_columns: &usize,
 ) -> std::io::Result<()> {
+    is_position_in_range(cell.row, cell.column, max_rows, max_columns)
+}
+
+pub fn is_position_in_range(
+    row: i32,
+    column: i32,
+    max_rows: &usize,
+    max_columns: &usize,
+) -> std::io::Result<()> {
 
-    if cell.column < 0 || cell.column > (*max_columns - 1) as i32 {
-        Err(Error::new(ErrorKind::Other, "Column value out of range"))
-    } else if cell.row < 0 || cell.row > (*max_rows - 1) as i32 {
-        Err(Error::new(ErrorKind::Other, "Row value out of range"))
+    if column < 0 || column > (*max_columns - 1) as i32 {
+        Err(rejection("column_out_of_range", "Column value out of range"))
+    } else if row < 0 || row > (*max_rows - 1) as i32 {
+        Err(rejection("row_out_of_range", "Row value out of range"))
     } else {
         Ok(())
     }
 }
 
 pub fn is_line_value_in_range(
-    line: &Json<Line>,
+    line: &Line,
     max_rows: &usize,
     max_columns: &usize,
 ) -> std::io::Result<()> {
 
 
     if line.direction != 1 && line.direction != 0 {
-        Err(Error::new(ErrorKind::Other, "Direction must either be horizontal or vertical"))
+        Err(rejection("invalid_direction", "Direction must either be horizontal or vertical"))
     // } else if line.column < 0 || line.column > (*max_columns - 1) as i32 {
     //     Err(Error::new(ErrorKind::Other, "Column value of start point is out of range"))
     // } else if line.row < 0 || line.row > (*max_rows - 1) as i32 {
@@ -0,0 +1,53 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+
+use serde::Serialize;
+
+//a change of the grid, data is the request that caused it
+#[derive(Clone, Serialize)]
+pub struct Event {
+    pub kind: String,
+    pub source: String,
+    pub data: serde_json::Value,
+}
+
+pub struct SharedEvents {
+    pub subscribers: Arc<Mutex<Vec<Sender<Event>>>>,
+}
+
+pub fn events_init() -> SharedEvents {
+    SharedEvents {
+        subscribers: Arc::new(Mutex::new(Vec::new())),
+    }
+}
+
+//every subscriber gets its own copy of all events that are published after it subscribed
+pub fn subscribe(events: &SharedEvents) -> Receiver<Event> {
+    let (sender, receiver) = mpsc::channel();
+    events
+        .subscribers
+        .lock()
+        .expect("events lock failed")
+        .push(sender);
+    receiver
+}
+
+//subscribers that went away are removed
+pub fn publish<T: Serialize>(events: &SharedEvents, kind: &str, source: &str, data: &T) {
+    let mut subscribers = events.subscribers.lock().expect("events lock failed");
+    if subscribers.is_empty() {
+        return;
+    }
+
+    let event = Event {
+        kind: String::from(kind),
+        source: String::from(source),
+        data: serde_json::to_value(data).unwrap_or(serde_json::Value::Null),
+    };
+    subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+}
+
+//ends every subscription, the subscribers get the events that are left and then stop
+pub fn close(events: &SharedEvents) {
+    events.subscribers.lock().expect("events lock failed").clear();
+}
@@ -0,0 +1,62 @@
+use std::fs::File;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+
+use gif::SetParameter;
+
+//the first frame of a .gif or .png file as rgb24 rows: width, height and pixels
+pub fn load(path: &Path) -> std::io::Result<(usize, usize, Vec<u8>)> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_lowercase());
+
+    match extension.as_ref().map(String::as_str) {
+        Some("gif") => load_gif(path),
+        Some("png") => load_png(path),
+        _ => Err(Error::new(
+            ErrorKind::Other,
+            format!("Images must be .gif or .png files: {}", path.display()),
+        )),
+    }
+}
+
+fn load_gif(path: &Path) -> std::io::Result<(usize, usize, Vec<u8>)> {
+    let to_io = |error: gif::DecodingError| Error::new(ErrorKind::Other, error.to_string());
+
+    let mut decoder = gif::Decoder::new(File::open(path)?);
+    decoder.set(gif::ColorOutput::RGBA);
+    let mut reader = decoder.read_info().map_err(to_io)?;
+    let frame = match reader.read_next_frame().map_err(to_io)? {
+        Some(frame) => frame,
+        None => return Err(Error::new(ErrorKind::Other, "The image has no frames")),
+    };
+
+    let pixels = frame
+        .buffer
+        .chunks(4)
+        .flat_map(|pixel| pixel[..3].iter().cloned())
+        .collect();
+    Ok((frame.width as usize, frame.height as usize, pixels))
+}
+
+//palettes and small bit depths are expanded to 8 bit samples, alpha is dropped
+fn load_png(path: &Path) -> std::io::Result<(usize, usize, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(File::open(path)?);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let (info, mut reader) = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut buffer)?;
+
+    let (color_type, _bits) = reader.output_color_type();
+    let pixels = buffer
+        .chunks(color_type.samples())
+        .flat_map(|pixel| match color_type {
+            png::ColorType::Grayscale | png::ColorType::GrayscaleAlpha => {
+                vec![pixel[0], pixel[0], pixel[0]]
+            }
+            _ => pixel[..3].to_vec(),
+        })
+        .collect();
+    Ok((info.width as usize, info.height as usize, pixels))
+}
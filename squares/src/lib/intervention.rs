@@ -0,0 +1,186 @@
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+
+use crate::lib::api::ApiIntervention;
+use crate::lib::data::{Grid, SharedGrid, RGB};
+use crate::lib::{color, draw, image, pattern, text};
+
+pub struct Intervention {
+    pub active: bool,
+    //when the program resumes on its own
+    pub until: Option<Instant>,
+}
+
+pub struct SharedIntervention {
+    pub intervention: Arc<Mutex<Intervention>>,
+}
+
+pub fn intervention_init() -> SharedIntervention {
+    SharedIntervention {
+        intervention: Arc::new(Mutex::new(Intervention {
+            active: false,
+            until: None,
+        })),
+    }
+}
+
+//pauses the program and covers the grid with the overlay. The layers stay as they are
+//and can still be drawn on, they are shown again on resume.
+pub fn start(
+    settings: &ApiIntervention,
+    colors: &[RGB],
+    sharedgrid: &SharedGrid,
+    shared_intervention: &SharedIntervention,
+    program_paused: &AtomicBool,
+) -> std::io::Result<()> {
+    //checked before any lock is taken, a panic here would poison the grid
+    let until = match settings.timeout {
+        Some(seconds) => match Instant::now().checked_add(Duration::from_secs(seconds)) {
+            Some(until) => Some(until),
+            None => return Err(Error::new(ErrorKind::Other, "Timeout too large")),
+        },
+        None => None,
+    };
+
+    //the image is loaded and the overlay built before the locks are taken, so loading a large
+    //file does not hold up drawing and the video loop
+    let (rows, columns, bottom_background) = {
+        let sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+        let bottom = &sharedgrid_data.layers[0].grid;
+        (bottom.grid.len(), bottom.grid[0].len(), bottom.background)
+    };
+    let background = match color::resolve_optional(&settings.color, colors)? {
+        Some(color) => color,
+        None => bottom_background,
+    };
+    let overlay = overlay(settings, colors, rows, columns, background)?;
+
+    let mut intervention = shared_intervention
+        .intervention
+        .lock()
+        .expect("intervention lock failed");
+    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+    sharedgrid_data.intervention = Some(overlay);
+    program_paused.store(true, Ordering::Relaxed);
+    intervention.active = true;
+    intervention.until = until;
+    Ok(())
+}
+
+fn overlay(
+    settings: &ApiIntervention,
+    colors: &[RGB],
+    rows: usize,
+    columns: usize,
+    background: RGB,
+) -> std::io::Result<Grid> {
+    let mut overlay = Grid {
+        grid: vec![vec![background; columns]; rows],
+        background,
+        indices: vec![vec![None; columns]; rows],
+        opaque: vec![vec![true; columns]; rows],
+    };
+
+    if settings.pattern.is_none() && settings.image.is_none() && settings.text.is_none() {
+        let params = pattern::default_params(&overlay);
+        pattern::fill_grid(&mut overlay, &pattern::Pattern::Checkerboard, &params);
+    }
+
+    if let Some(overlay_pattern) = &settings.pattern {
+        let (new_pattern, params) = draw::pattern_params(overlay_pattern, colors, &overlay)?;
+        pattern::fill_grid(&mut overlay, &new_pattern, &params);
+    }
+
+    if let Some(path) = &settings.image {
+        let (width, height, pixels) = image::load(Path::new(path))?;
+        draw::image(width, height, &pixels, &mut overlay)?;
+    }
+
+    //the text stands still, nothing moves during an intervention
+    if let Some(overlay_text) = &settings.text {
+        let (color, _alpha) = color::resolve(&overlay_text.color, colors)?;
+        let column = overlay_text.column.unwrap_or(0);
+        text::draw_text(
+            &mut overlay,
+            &overlay_text.text,
+            overlay_text.row,
+            column,
+            color,
+        );
+    }
+    Ok(overlay)
+}
+
+//removes the overlay and lets the program go on where it stopped
+pub fn resume(
+    sharedgrid: &SharedGrid,
+    shared_intervention: &SharedIntervention,
+    program_paused: &AtomicBool,
+) {
+    let mut intervention = shared_intervention
+        .intervention
+        .lock()
+        .expect("intervention lock failed");
+    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+
+    sharedgrid_data.intervention = None;
+    program_paused.store(false, Ordering::Relaxed);
+    intervention.active = false;
+    intervention.until = None;
+}
+
+//resumes when the timeout has passed, called from the video loop
+pub fn tick(
+    shared_intervention: &SharedIntervention,
+    sharedgrid: &SharedGrid,
+    program_paused: &AtomicBool,
+) {
+    let due = {
+        let intervention = shared_intervention
+            .intervention
+            .lock()
+            .expect("intervention lock failed");
+        match intervention.until {
+            Some(until) => intervention.active && Instant::now() >= until,
+            None => false,
+        }
+    };
+
+    if due {
+        println!("intervention timed out");
+        resume(sharedgrid, shared_intervention, program_paused);
+    }
+}
+
+pub fn active(shared_intervention: &SharedIntervention) -> bool {
+    shared_intervention
+        .intervention
+        .lock()
+        .expect("intervention lock failed")
+        .active
+}
+
+pub fn status(shared_intervention: &SharedIntervention) -> serde_json::Value {
+    let intervention = shared_intervention
+        .intervention
+        .lock()
+        .expect("intervention lock failed");
+    let remaining = intervention.until.map(|until| {
+        let now = Instant::now();
+        if until > now {
+            (until - now).as_secs()
+        } else {
+            0
+        }
+    });
+
+    json!({
+        "active": intervention.active,
+        "remaining": remaining,
+    })
+}
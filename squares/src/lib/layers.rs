@@ -0,0 +1,227 @@
+use crate::lib::blend::{self, BlendMode};
+use crate::lib::data::{Grid, Layer, Layers, RGB};
+use crate::lib::err;
+use crate::lib::pattern::{self, Pattern};
+
+pub const DEFAULT_LAYER: &str = "content";
+
+//a background, a content and an overlay layer, every layer starts in the background color
+pub fn layers_init(nx_cells: i32, ny_cells: i32, background: RGB) -> Layers {
+    let mut layers = Layers {
+        layers: Vec::new(),
+        intervention: None,
+    };
+
+    for (z_index, name) in ["background", DEFAULT_LAYER, "overlay"].iter().enumerate() {
+        layers.layers.push(new_layer(
+            name,
+            z_index as i32,
+            nx_cells,
+            ny_cells,
+            background,
+        ));
+    }
+    layers
+}
+
+pub fn new_layer(name: &str, z_index: i32, nx_cells: i32, ny_cells: i32, background: RGB) -> Layer {
+    let grid = Grid {
+        grid: vec![vec![background; nx_cells as usize]; ny_cells as usize],
+        background,
+        indices: vec![vec![None; nx_cells as usize]; ny_cells as usize],
+        opaque: vec![vec![false; nx_cells as usize]; ny_cells as usize],
+    };
+
+    Layer {
+        name: String::from(name),
+        grid,
+        z_index,
+        visible: true,
+        opacity: 1.0,
+    }
+}
+
+//returns the grid of the named layer, or of the content layer if no name is given
+pub fn layer_mut<'a>(
+    layers: &'a mut Layers,
+    name: &Option<String>,
+) -> std::io::Result<&'a mut Grid> {
+    let name = match name {
+        Some(name) => name.as_str(),
+        None => DEFAULT_LAYER,
+    };
+
+    match layers.layers.iter_mut().find(|layer| layer.name == name) {
+        Some(layer) => Ok(&mut layer.grid),
+        None => Err(err::rejection("unknown_layer", "Unknown layer")),
+    }
+}
+
+//keeps the layers ordered from bottom to top
+pub fn sort_layers(layers: &mut Layers) {
+    layers.layers.sort_by_key(|layer| layer.z_index);
+}
+
+//fills the layer with its background color and makes every cell transparent
+pub fn erase(grid: &mut Grid) {
+    for row in 0..grid.grid.len() {
+        for column in 0..grid.grid[row].len() {
+            grid.grid[row][column] = grid.background;
+            grid.indices[row][column] = None;
+            grid.opaque[row][column] = false;
+        }
+    }
+}
+
+//without a color, a solid clear erases the layer. Everything else paints an opaque fill.
+pub fn clear_layer(grid: &mut Grid, clear_pattern: &Pattern, color: Option<RGB>) {
+    match (clear_pattern, color) {
+        (Pattern::Solid, None) => erase(grid),
+        (_, color) => {
+            let mut params = pattern::default_params(grid);
+            params.color = color.unwrap_or(grid.background);
+            pattern::fill_grid(grid, clear_pattern, &params);
+        }
+    }
+}
+
+//merges all visible layers from bottom to top into one grid.
+//The bottom layer is opaque, in the layers above only the cells that were drawn are shown.
+pub fn composite(layers: &Layers) -> Grid {
+    let bottom = &layers.layers[0].grid;
+    let (rows, columns) = (bottom.grid.len(), bottom.grid[0].len());
+    //paint marks the cells it writes, so the output needs the same size in every part
+    let mut output = Grid {
+        grid: vec![vec![bottom.background; columns]; rows],
+        background: bottom.background,
+        indices: vec![vec![None; columns]; rows],
+        opaque: vec![vec![false; columns]; rows],
+    };
+
+    for (i, layer) in layers.layers.iter().enumerate() {
+        if !layer.visible {
+            continue;
+        }
+        let alpha = (layer.opacity * 255.0).round() as u8;
+
+        for (row, cells) in layer.grid.grid.iter().enumerate() {
+            for (column, color) in cells.iter().enumerate() {
+                if i > 0 && !layer.grid.opaque[row][column] {
+                    continue;
+                }
+                blend::paint(&mut output, row, column, *color, alpha, &BlendMode::Normal);
+            }
+        }
+    }
+
+    if let Some(intervention) = &layers.intervention {
+        output.grid = intervention.grid.clone();
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gray(value: u8) -> RGB {
+        RGB {
+            red: value,
+            green: value,
+            blue: value,
+        }
+    }
+
+    //a background and a content layer, 1 row of 2 cells
+    fn two_layers() -> Layers {
+        let mut layers = layers_init(2, 1, gray(0));
+        layers.layers.truncate(2);
+        blend::paint(
+            &mut layers.layers[0].grid,
+            0,
+            0,
+            gray(10),
+            255,
+            &BlendMode::Normal,
+        );
+        blend::paint(
+            &mut layers.layers[0].grid,
+            0,
+            1,
+            gray(20),
+            255,
+            &BlendMode::Normal,
+        );
+        layers
+    }
+
+    #[test]
+    fn composite_has_the_size_of_the_grid() {
+        let output = composite(&layers_init(3, 2, gray(0)));
+        assert_eq!(output.grid, vec![vec![gray(0); 3]; 2]);
+        assert_eq!(output.indices, vec![vec![None; 3]; 2]);
+        assert_eq!(output.opaque, vec![vec![true; 3]; 2]);
+    }
+
+    #[test]
+    fn transparent_cells_show_the_layer_below() {
+        let mut layers = two_layers();
+        blend::paint(
+            &mut layers.layers[1].grid,
+            0,
+            1,
+            gray(200),
+            255,
+            &BlendMode::Normal,
+        );
+
+        let output = composite(&layers);
+        assert_eq!(output.grid[0], vec![gray(10), gray(200)]);
+    }
+
+    #[test]
+    fn layers_are_blended_with_their_opacity() {
+        let mut layers = two_layers();
+        layers.layers[1].opacity = 0.5;
+        blend::paint(
+            &mut layers.layers[1].grid,
+            0,
+            0,
+            gray(210),
+            255,
+            &BlendMode::Normal,
+        );
+
+        let output = composite(&layers);
+        //128 of 255 of the layer above
+        assert_eq!(output.grid[0], vec![gray(110), gray(20)]);
+    }
+
+    #[test]
+    fn hidden_and_erased_layers_are_left_out() {
+        let mut layers = two_layers();
+        blend::paint(
+            &mut layers.layers[1].grid,
+            0,
+            0,
+            gray(200),
+            255,
+            &BlendMode::Normal,
+        );
+        layers.layers[1].visible = false;
+        assert_eq!(composite(&layers).grid[0], vec![gray(10), gray(20)]);
+
+        layers.layers[1].visible = true;
+        erase(&mut layers.layers[1].grid);
+        assert_eq!(composite(&layers).grid[0], vec![gray(10), gray(20)]);
+    }
+
+    #[test]
+    fn the_intervention_covers_everything() {
+        let mut layers = two_layers();
+        let mut overlay = layers.layers[0].grid.clone();
+        overlay.grid = vec![vec![gray(99); 2]];
+        layers.intervention = Some(overlay);
+        assert_eq!(composite(&layers).grid[0], vec![gray(99), gray(99)]);
+    }
+}
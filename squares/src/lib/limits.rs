@@ -0,0 +1,123 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+//clients that sent a request within this time count as active in fair-share mode
+const ACTIVE: Duration = Duration::from_secs(10);
+//clients that are silent for longer are forgotten
+const FORGET: Duration = Duration::from_secs(60);
+
+#[derive(Clone, Copy)]
+pub struct LimitSettings {
+    pub requests_per_second: Option<f64>,
+    pub cells_per_second: Option<f64>,
+    //the limits are shared by all active clients instead of applying to every client
+    pub fair_share: bool,
+}
+
+//a token bucket that holds at most one second worth of its rate
+struct Allowance {
+    tokens: f64,
+    last: Instant,
+}
+
+struct Bucket {
+    requests: Allowance,
+    cells: Allowance,
+    last_seen: Instant,
+}
+
+pub struct Limits {
+    pub settings: LimitSettings,
+    buckets: HashMap<String, Bucket>,
+}
+
+pub struct SharedLimits {
+    pub limits: Arc<Mutex<Limits>>,
+}
+
+pub fn limits_init(settings: LimitSettings) -> SharedLimits {
+    SharedLimits {
+        limits: Arc::new(Mutex::new(Limits {
+            settings,
+            buckets: HashMap::new(),
+        })),
+    }
+}
+
+//false if the client sent too many requests
+pub fn take_request(shared_limits: &SharedLimits, client: &str) -> bool {
+    let mut limits = shared_limits.limits.lock().expect("limits lock failed");
+    let rate = limits.settings.requests_per_second;
+    take(&mut limits, client, rate, 1.0, |bucket| &mut bucket.requests)
+}
+
+//false if the client drew too many cells
+pub fn take_cells(shared_limits: &SharedLimits, client: &str, cells: usize) -> bool {
+    let mut limits = shared_limits.limits.lock().expect("limits lock failed");
+    let rate = limits.settings.cells_per_second;
+    take(&mut limits, client, rate, cells as f64, |bucket| &mut bucket.cells)
+}
+
+fn take(
+    limits: &mut Limits,
+    client: &str,
+    rate: Option<f64>,
+    amount: f64,
+    allowance: fn(&mut Bucket) -> &mut Allowance,
+) -> bool {
+    let rate = match rate {
+        Some(rate) => rate,
+        None => return true,
+    };
+    let now = Instant::now();
+    limits
+        .buckets
+        .retain(|_, bucket| now.duration_since(bucket.last_seen) < FORGET);
+
+    //in fair-share mode, every active client gets an equal part, new clients included
+    let rate = if limits.settings.fair_share {
+        let active = limits
+            .buckets
+            .iter()
+            .filter(|(name, bucket)| {
+                name.as_str() != client && now.duration_since(bucket.last_seen) < ACTIVE
+            })
+            .count()
+            + 1;
+        rate / active as f64
+    } else {
+        rate
+    };
+
+    //new clients start with full buckets
+    let bucket = limits
+        .buckets
+        .entry(String::from(client))
+        .or_insert_with(|| Bucket {
+            requests: Allowance {
+                tokens: f64::MAX,
+                last: now,
+            },
+            cells: Allowance {
+                tokens: f64::MAX,
+                last: now,
+            },
+            last_seen: now,
+        });
+    bucket.last_seen = now;
+
+    let allowance = allowance(bucket);
+    let elapsed = now.duration_since(allowance.last);
+    let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9;
+    allowance.tokens = (allowance.tokens + elapsed * rate).min(rate);
+    allowance.last = now;
+
+    //a request larger than the bucket passes when the bucket is full, and has to be paid back
+    if allowance.tokens >= amount.min(rate) {
+        allowance.tokens -= amount;
+        true
+    } else {
+        false
+    }
+}
@@ -0,0 +1,281 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Request, Response};
+
+use crate::lib::data::{Grid, RGB};
+use crate::lib::err;
+
+//the frame rate is measured over this time
+const FPS_WINDOW: Duration = Duration::from_secs(1);
+//upper bounds of the frame render time histogram, in seconds
+const RENDER_BUCKETS: [f64; 8] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0];
+
+pub struct Metrics {
+    pub started: Instant,
+    pub frames: u64,
+    pub last_frame: Option<Instant>,
+    //frames per second the video loop actually drew
+    pub fps: f64,
+    window_start: Instant,
+    window_frames: u64,
+    //time the video loop waited for the grid lock
+    pub lock_wait: Duration,
+    pub lock_wait_max: Duration,
+    //frames per bucket of RENDER_BUCKETS that display_frame drew within its time
+    render_buckets: [u64; 8],
+    pub render_time: Duration,
+    //cells that look different than in the frame before
+    pub cells_changed: u64,
+    pub cells_per_second: f64,
+    window_cells: u64,
+    previous_frame: Option<Vec<Vec<RGB>>>,
+    //"<method> <path>" of the route
+    pub requests: BTreeMap<String, u64>,
+    pub rejected: BTreeMap<String, u64>,
+}
+
+pub struct SharedMetrics {
+    pub metrics: Arc<Mutex<Metrics>>,
+}
+
+//counts the requests per route and the rejected requests per reason
+pub struct RequestCounter {
+    pub metrics: SharedMetrics,
+}
+
+pub fn metrics_init() -> SharedMetrics {
+    let now = Instant::now();
+    SharedMetrics {
+        metrics: Arc::new(Mutex::new(Metrics {
+            started: now,
+            frames: 0,
+            last_frame: None,
+            fps: 0.0,
+            window_start: now,
+            window_frames: 0,
+            lock_wait: Duration::from_secs(0),
+            lock_wait_max: Duration::from_secs(0),
+            render_buckets: [0; 8],
+            render_time: Duration::from_secs(0),
+            cells_changed: 0,
+            cells_per_second: 0.0,
+            window_cells: 0,
+            previous_frame: None,
+            requests: BTreeMap::new(),
+            rejected: BTreeMap::new(),
+        })),
+    }
+}
+
+//called by the video loop after every frame with the displayed grid, the time it waited for
+//the grid and the time drawing the frame took
+pub fn frame(shared_metrics: &SharedMetrics, grid: Grid, lock_wait: Duration, render: Duration) {
+    let mut metrics = shared_metrics.metrics.lock().expect("metrics lock failed");
+    let now = Instant::now();
+
+    metrics.frames += 1;
+    metrics.last_frame = Some(now);
+    metrics.lock_wait += lock_wait;
+    if lock_wait > metrics.lock_wait_max {
+        metrics.lock_wait_max = lock_wait;
+    }
+
+    metrics.render_time += render;
+    let render_seconds = seconds(render);
+    for (i, bound) in RENDER_BUCKETS.iter().enumerate() {
+        if render_seconds <= *bound {
+            metrics.render_buckets[i] += 1;
+        }
+    }
+
+    let changed: usize = match &metrics.previous_frame {
+        Some(previous) => previous
+            .iter()
+            .zip(grid.grid.iter())
+            .map(|(before, after)| {
+                before
+                    .iter()
+                    .zip(after.iter())
+                    .filter(|(before, after)| before != after)
+                    .count()
+            })
+            .sum(),
+        None => 0,
+    };
+    metrics.previous_frame = Some(grid.grid);
+    metrics.cells_changed += changed as u64;
+
+    metrics.window_frames += 1;
+    metrics.window_cells += changed as u64;
+    let elapsed = now.duration_since(metrics.window_start);
+    if elapsed >= FPS_WINDOW {
+        metrics.fps = metrics.window_frames as f64 / seconds(elapsed);
+        metrics.cells_per_second = metrics.window_cells as f64 / seconds(elapsed);
+        metrics.window_start = now;
+        metrics.window_frames = 0;
+        metrics.window_cells = 0;
+    }
+}
+
+//requests that were answered with an error message, the reasons of error statuses are
+//counted by the RequestCounter
+pub fn rejected(shared_metrics: &SharedMetrics, reason: &str) {
+    let mut metrics = shared_metrics.metrics.lock().expect("metrics lock failed");
+    *metrics.rejected.entry(String::from(reason)).or_insert(0) += 1;
+}
+
+pub fn seconds(duration: Duration) -> f64 {
+    duration.as_secs() as f64 + f64::from(duration.subsec_nanos()) / 1e9
+}
+
+fn rejection_reason(code: u16) -> Option<&'static str> {
+    match code {
+        400 => Some("bad_request"),
+        401 => Some("unauthorized"),
+        403 => Some("forbidden"),
+        404 => Some("not_found"),
+        413 => Some("payload_too_large"),
+        422 => Some("unprocessable"),
+        429 => Some("too_many_requests"),
+        503 => Some("shutting_down"),
+        500..=599 => Some("server_error"),
+        _ => None,
+    }
+}
+
+//the metrics in the Prometheus text format
+pub fn prometheus(shared_metrics: &SharedMetrics, paused: bool) -> String {
+    let metrics = shared_metrics.metrics.lock().expect("metrics lock failed");
+    let mut text = String::new();
+
+    let uptime = seconds(metrics.started.elapsed());
+    gauge(
+        &mut text,
+        "squares_uptime_seconds",
+        "Seconds since the start.",
+        uptime,
+    );
+    let paused = if paused { 1.0 } else { 0.0 };
+    gauge(
+        &mut text,
+        "squares_paused",
+        "1 while the program is paused.",
+        paused,
+    );
+    gauge(
+        &mut text,
+        "squares_fps",
+        "Frames drawn in the last second.",
+        metrics.fps,
+    );
+
+    counter(
+        &mut text,
+        "squares_frames_total",
+        "Frames drawn by the video loop.",
+        metrics.frames as f64,
+    );
+    counter(
+        &mut text,
+        "squares_cells_changed_total",
+        "Cells that changed between two frames.",
+        metrics.cells_changed as f64,
+    );
+    counter(
+        &mut text,
+        "squares_lock_wait_seconds_total",
+        "Time the video loop waited for the grid.",
+        seconds(metrics.lock_wait),
+    );
+
+    let name = "squares_frame_render_seconds";
+    header(
+        &mut text,
+        name,
+        "Time it took to draw a frame.",
+        "histogram",
+    );
+    for (bound, count) in RENDER_BUCKETS.iter().zip(metrics.render_buckets.iter()) {
+        let _ = writeln!(text, "{}_bucket{{le=\"{}\"}} {}", name, bound, count);
+    }
+    let _ = writeln!(text, "{}_bucket{{le=\"+Inf\"}} {}", name, metrics.frames);
+    let _ = writeln!(text, "{}_sum {}", name, seconds(metrics.render_time));
+    let _ = writeln!(text, "{}_count {}", name, metrics.frames);
+
+    let name = "squares_requests_total";
+    header(&mut text, name, "HTTP requests per route.", "counter");
+    for (route, count) in metrics.requests.iter() {
+        let _ = writeln!(text, "{}{{route=\"{}\"}} {}", name, escape(route), count);
+    }
+
+    let name = "squares_rejected_requests_total";
+    header(
+        &mut text,
+        name,
+        "HTTP requests that were rejected, by reason.",
+        "counter",
+    );
+    for (reason, count) in metrics.rejected.iter() {
+        let _ = writeln!(text, "{}{{reason=\"{}\"}} {}", name, reason, count);
+    }
+
+    let name = "squares_validation_failures_total";
+    header(
+        &mut text,
+        name,
+        "Requests whose values failed a check.",
+        "counter",
+    );
+    //counted where the handlers reject a request, not where drawing clips cells at the edge
+    for check in err::VALIDATION_CHECKS.iter() {
+        let count = metrics.rejected.get(*check).cloned().unwrap_or(0);
+        let _ = writeln!(text, "{}{{check=\"{}\"}} {}", name, check, count);
+    }
+    text
+}
+
+fn header(text: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, kind);
+}
+
+fn gauge(text: &mut String, name: &str, help: &str, value: f64) {
+    header(text, name, help, "gauge");
+    let _ = writeln!(text, "{} {}", name, value);
+}
+
+fn counter(text: &mut String, name: &str, help: &str, value: f64) {
+    header(text, name, help, "counter");
+    let _ = writeln!(text, "{} {}", name, value);
+}
+
+//label values are quoted, so quotes and backslashes are escaped
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+impl Fairing for RequestCounter {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request counter",
+            kind: Kind::Response,
+        }
+    }
+
+    fn on_response(&self, request: &Request, response: &mut Response) {
+        let route = match request.route() {
+            Some(route) => format!("{} {}", route.method, route.uri.path()),
+            None => String::from("unmatched"),
+        };
+
+        let mut metrics = self.metrics.metrics.lock().expect("metrics lock failed");
+        *metrics.requests.entry(route).or_insert(0) += 1;
+        if let Some(reason) = rejection_reason(response.status().code) {
+            *metrics.rejected.entry(String::from(reason)).or_insert(0) += 1;
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use sdl2::video::Window;
 
 use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
@@ -9,30 +10,45 @@ use sdl2::video::FullscreenType::{self, Desktop, Off};
 use sdl2::EventPump;
 
 pub mod api;
+pub mod audit;
+pub mod auth;
+pub mod automata;
+pub mod blend;
+pub mod color;
 pub mod data;
+pub mod dmx;
+pub mod draw;
 pub mod err;
+pub mod events;
+pub mod font;
+pub mod image;
+pub mod intervention;
+pub mod layers;
+pub mod limits;
+pub mod metrics;
+pub mod mqtt;
+pub mod output;
+pub mod palette;
+pub mod pattern;
+pub mod pixelflut;
+pub mod recorder;
+pub mod regions;
 pub mod requests;
+pub mod schedule;
+pub mod shutdown;
+pub mod text;
+pub mod timelapse;
+pub mod udp;
+pub mod wire;
 
 use data::{Grid, SharedGrid, RGB, ScreenResolution};
 
-//creates a grid with ncells*ncells initialized with cell in a color
-pub fn grid_init(nx_cells: i32, ny_cells: i32) -> SharedGrid {
-    let mut grid_vector = Vec::new();
-
-    for row in 0..ny_cells {
-        grid_vector.push(Vec::new());
-        for _column in 0..nx_cells {
-            grid_vector[row as usize].push(RGB {
-                red: 35_u8,
-                green: 15_u8,
-                blue: 13_u8,
-            });
-        }
-    }
-    let grid = Grid { grid: grid_vector };
+//creates the layers of the grid with ncells*ncells initialized with cell in the background color
+pub fn grid_init(nx_cells: i32, ny_cells: i32, background: RGB) -> SharedGrid {
+    let layers = layers::layers_init(nx_cells, ny_cells, background);
 
     let output_grid = SharedGrid {
-        sharedgrid: Arc::new(Mutex::new(grid)),
+        sharedgrid: Arc::new(Mutex::new(layers)),
     };
 
     output_grid
@@ -64,16 +80,21 @@ pub fn display_cell(
     }
 }
 
-//displays the whole grid by repeatedly calling display_cell on every cell
+//displays the whole grid by repeatedly calling display_cell on every cell,
+//returns the displayed grid and how long it waited for the grid lock
 pub fn display_frame(
     renderer: &mut Canvas<Window>,
     shared_grid: &SharedGrid,
     nx_cells: &i32,
     ny_cells: &i32,
     cell_width: &i32,
-) {
+) -> (Grid, Duration) {
     let sharedgrid_data = &shared_grid.sharedgrid;
-    let grid_data = sharedgrid_data.lock().expect("grid lock failed");
+    let waiting = Instant::now();
+    let sharedgrid_data = sharedgrid_data.lock().expect("grid lock failed");
+    let lock_wait = waiting.elapsed();
+    let grid_data = layers::composite(&sharedgrid_data);
+    drop(sharedgrid_data);
 
     renderer.set_draw_color(Color::RGB(0, 0, 0));
     renderer.clear();
@@ -84,6 +105,7 @@ pub fn display_frame(
         }
     }
     renderer.present();
+    (grid_data, lock_wait)
 }
 
 pub fn toggle_fullscreen(canvas: &mut Canvas<Window>, canvas_width: i32, canvas_height: i32) {
@@ -139,54 +161,63 @@ pub fn get_screen_resolution(canvas: &mut Canvas<Window>) -> ScreenResolution {
     screen_resolution
 }
 
+//resets every layer to its background color
 pub fn clear_grid(shared_grid: &SharedGrid) {
     println!("clearing grid");
 
     let mut sharedgrid_data = shared_grid.sharedgrid.lock().expect("grid lock failed");
-    let max_rows = &sharedgrid_data.grid.len();
-    let max_columns = &sharedgrid_data.grid[0].len();
-
-    for row in 0..*max_rows as i32 {
-        for column in 0..*max_columns as i32 {
-            sharedgrid_data.grid[row as usize][column as usize] = RGB {
-                red: 35_u8,
-                green: 15_u8,
-                blue: 13_u8,
-            };
-        }
+    for layer in sharedgrid_data.layers.iter_mut() {
+        layers::erase(&mut layer.grid);
     }
 }
 
-pub fn make_checker_board(shared_grid: &SharedGrid) {
-    println!("clearing grid");
+//number of cells in every layer
+pub fn grid_cells(shared_grid: &SharedGrid) -> usize {
+    let sharedgrid_data = shared_grid.sharedgrid.lock().expect("grid lock failed");
+    let grid_data = &sharedgrid_data.layers[0].grid;
+    grid_data.grid.len() * grid_data.grid[0].len()
+}
+
+pub fn grid_rows(shared_grid: &SharedGrid) -> i32 {
+    let sharedgrid_data = shared_grid.sharedgrid.lock().expect("grid lock failed");
+    sharedgrid_data.layers[0].grid.grid.len() as i32
+}
 
+pub fn grid_columns(shared_grid: &SharedGrid) -> i32 {
+    let sharedgrid_data = shared_grid.sharedgrid.lock().expect("grid lock failed");
+    sharedgrid_data.layers[0].grid.grid[0].len() as i32
+}
+
+pub fn make_checker_board(shared_grid: &SharedGrid) {
     let mut sharedgrid_data = shared_grid.sharedgrid.lock().expect("grid lock failed");
-    let max_rows = &sharedgrid_data.grid.len();
-    let max_columns = &sharedgrid_data.grid[0].len();
-
-    for row in 0..*max_rows as i32 {
-        if row % 2 == 0 {
-            for column in 0..*max_columns as i32 {
-                if column % 2 == 0 {
-                    sharedgrid_data.grid[row as usize][column as usize] = RGB {
-                        red: 255,
-                        green: 255,
-                        blue: 255,
-                    };
-                }
-            }
-        } else {
-            for column in 0..*max_columns as i32 {
-                if column % 2 == 1 {
-                    sharedgrid_data.grid[row as usize][column as usize] = RGB {
-                        red: 255,
-                        green: 255,
-                        blue: 255,
-                    };
-                }
-            }
-        }
+    let grid_data = layers::layer_mut(&mut sharedgrid_data, &None).expect("content layer exists");
+    let params = pattern::default_params(grid_data);
+    pattern::fill_grid(grid_data, &pattern::Pattern::Checkerboard, &params);
+}
+
+//parses "red,green,blue", a hex color or a css color name from the command line
+pub fn parse_rgb(input: &str) -> Result<RGB, String> {
+    let values: Vec<&str> = input.split(',').collect();
+    if values.len() != 3 {
+        return match color::parse_color(input) {
+            Ok((rgb, _alpha)) => Ok(rgb),
+            Err(error) => Err(error.to_string()),
+        };
+    }
+
+    let mut channels = [0_u8; 3];
+    for (i, value) in values.iter().enumerate() {
+        channels[i] = value
+            .trim()
+            .parse::<u8>()
+            .map_err(|_| String::from("Color values must be between 0 and 255"))?;
     }
+
+    Ok(RGB {
+        red: channels[0],
+        green: channels[1],
+        blue: channels[2],
+    })
 }
 
 pub fn center_rect(res_width: i32, res_height: i32, canvas_width: i32, canvas_height: i32) -> Rect {
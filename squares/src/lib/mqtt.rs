@@ -0,0 +1,387 @@
+use std::io::{BufReader, Error, ErrorKind, Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::sync::mpsc::RecvTimeoutError;
+use std::{thread, time};
+
+use crate::lib::api::{ApiGrid, Cell, Line};
+use crate::lib::data::SharedGrid;
+use crate::lib::events::{self, SharedEvents};
+use crate::lib::palette::{self, SharedPalettes};
+use crate::lib::regions;
+use crate::lib::{draw, wire};
+
+//a minimal MQTT 3.1.1 client, messages are sent and received with QoS 0
+
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const SUBSCRIBE: u8 = 0x82;
+const PINGREQ: u8 = 0xC0;
+
+const KEEP_ALIVE: u16 = 60;
+
+pub struct MqttSettings {
+    //host:port of the broker
+    pub broker: String,
+    pub client_id: String,
+    //the last level of a topic tells what is in the message: cell, line or grid
+    pub topics: Vec<String>,
+    //changes of the grid are published here
+    pub events_topic: String,
+}
+
+//stays connected to the broker, a lost connection is opened again after a few seconds
+pub fn run(
+    settings: MqttSettings,
+    sharedgrid: SharedGrid,
+    shared_palettes: SharedPalettes,
+    shared_events: SharedEvents,
+) {
+    loop {
+        if let Err(error) = connect(&settings, &sharedgrid, &shared_palettes, &shared_events) {
+            println!("MQTT connection to {} failed: {}", settings.broker, error);
+        }
+        thread::sleep(time::Duration::from_secs(5));
+    }
+}
+
+fn connect(
+    settings: &MqttSettings,
+    sharedgrid: &SharedGrid,
+    shared_palettes: &SharedPalettes,
+    shared_events: &SharedEvents,
+) -> std::io::Result<()> {
+    let mut stream = TcpStream::connect(settings.broker.as_str())?;
+
+    //protocol name, level 4, clean session, keep alive and the client id
+    let mut connect = encode_string("MQTT");
+    connect.extend_from_slice(&[4, 0x02, (KEEP_ALIVE >> 8) as u8, KEEP_ALIVE as u8]);
+    connect.extend(encode_string(&settings.client_id));
+    stream.write_all(&packet(CONNECT, &connect))?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let (header, body) = read_packet(&mut reader)?;
+    if header & 0xF0 != CONNACK || body.len() < 2 || body[1] != 0 {
+        return Err(Error::new(ErrorKind::Other, "Broker refused the connection"));
+    }
+
+    let mut subscribe = vec![0, 1];
+    for topic in settings.topics.iter() {
+        subscribe.extend(encode_string(topic));
+        subscribe.push(0);
+    }
+    stream.write_all(&packet(SUBSCRIBE, &subscribe))?;
+    println!("MQTT connected to {}", settings.broker);
+
+    //events and pings are written from their own thread, so reading never waits on them
+    let writer = stream.try_clone()?;
+    let receiver = events::subscribe(shared_events);
+    let events_topic = settings.events_topic.clone();
+    thread::spawn(move || {
+        let _ = write_events(writer, receiver, events_topic);
+    });
+
+    let result = read_messages(&mut reader, sharedgrid, shared_palettes, shared_events);
+    //stops the writing thread as well
+    let _ = stream.shutdown(Shutdown::Both);
+    result
+}
+
+fn read_messages<R: Read>(
+    reader: &mut R,
+    sharedgrid: &SharedGrid,
+    shared_palettes: &SharedPalettes,
+    shared_events: &SharedEvents,
+) -> std::io::Result<()> {
+    loop {
+        let (header, body) = read_packet(reader)?;
+        if header & 0xF0 != PUBLISH {
+            continue;
+        }
+
+        if body.len() < 2 {
+            continue;
+        }
+        let topic_length = wire::read_u16(&body, 0) as usize;
+        let topic = match body.get(2..2 + topic_length) {
+            Some(topic) => String::from_utf8_lossy(topic).into_owned(),
+            None => continue,
+        };
+        //messages with a QoS above 0 carry a packet id before the payload
+        let mut payload_start = 2 + topic_length;
+        if header & 0x06 != 0 {
+            payload_start += 2;
+        }
+        let payload = match body.get(payload_start..) {
+            Some(payload) => payload,
+            None => continue,
+        };
+
+        //there is no one to answer, messages that can not be drawn are dropped
+        let _ = apply_message(&topic, payload, sharedgrid, shared_palettes, shared_events);
+    }
+}
+
+//validates and draws a message the same way as the http requests
+pub fn apply_message(
+    topic: &str,
+    payload: &[u8],
+    sharedgrid: &SharedGrid,
+    shared_palettes: &SharedPalettes,
+    shared_events: &SharedEvents,
+) -> std::io::Result<()> {
+    let colors = palette::active_colors(shared_palettes);
+    let kind = topic.rsplit('/').next().unwrap_or("");
+
+    match kind {
+        "cell" => {
+            let cell: Cell = parse_payload(payload)?;
+            draw::cell(&cell, &colors, &regions::everywhere(), sharedgrid)?;
+            events::publish(shared_events, kind, "mqtt", &cell);
+        }
+        "line" => {
+            let line: Line = parse_payload(payload)?;
+            draw::line(&line, &colors, &regions::everywhere(), sharedgrid)?;
+            events::publish(shared_events, kind, "mqtt", &line);
+        }
+        "grid" => {
+            let grid: ApiGrid = parse_payload(payload)?;
+            draw::grid(&grid, &colors, &regions::everywhere(), sharedgrid)?;
+            events::publish(shared_events, kind, "mqtt", &grid);
+        }
+        _ => return Err(Error::new(ErrorKind::Other, "Unknown topic")),
+    }
+    Ok(())
+}
+
+fn parse_payload<T: serde::de::DeserializeOwned>(payload: &[u8]) -> std::io::Result<T> {
+    match serde_json::from_slice(payload) {
+        Ok(value) => Ok(value),
+        Err(error) => Err(Error::new(ErrorKind::Other, error.to_string())),
+    }
+}
+
+fn write_events(
+    mut writer: TcpStream,
+    receiver: std::sync::mpsc::Receiver<events::Event>,
+    events_topic: String,
+) -> std::io::Result<()> {
+    //the broker drops clients that are silent for longer than the keep alive
+    let ping_interval = time::Duration::from_secs(u64::from(KEEP_ALIVE) / 2);
+
+    loop {
+        match receiver.recv_timeout(ping_interval) {
+            Ok(event) => {
+                let mut message = encode_string(&events_topic);
+                message.extend(serde_json::to_vec(&event).unwrap_or_default());
+                writer.write_all(&packet(PUBLISH, &message))?;
+            }
+            Err(RecvTimeoutError::Timeout) => writer.write_all(&[PINGREQ, 0])?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+    }
+}
+
+//a fixed header with the remaining length, followed by the body
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![header];
+    let mut length = body.len();
+    loop {
+        let mut byte = (length % 128) as u8;
+        length /= 128;
+        if length > 0 {
+            byte |= 0x80;
+        }
+        packet.push(byte);
+        if length == 0 {
+            break;
+        }
+    }
+    packet.extend_from_slice(body);
+    packet
+}
+
+fn read_packet<R: Read>(reader: &mut R) -> std::io::Result<(u8, Vec<u8>)> {
+    let mut byte = [0; 1];
+    reader.read_exact(&mut byte)?;
+    let header = byte[0];
+
+    let mut length = 0;
+    let mut multiplier = 1;
+    loop {
+        reader.read_exact(&mut byte)?;
+        length += (byte[0] & 0x7F) as usize * multiplier;
+        if byte[0] & 0x80 == 0 {
+            break;
+        }
+        multiplier *= 128;
+        if multiplier > 128 * 128 * 128 {
+            return Err(Error::new(ErrorKind::Other, "Invalid MQTT packet length"));
+        }
+    }
+
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body)?;
+    Ok((header, body))
+}
+
+fn encode_string(text: &str) -> Vec<u8> {
+    let mut encoded = vec![(text.len() >> 8) as u8, text.len() as u8];
+    encoded.extend_from_slice(text.as_bytes());
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, ErrorKind};
+
+    use crate::lib;
+    use crate::lib::data::RGB;
+
+    const BLACK: RGB = RGB {
+        red: 0,
+        green: 0,
+        blue: 0,
+    };
+
+    //the remaining length of a packet, without the fixed header byte
+    fn remaining_length(length: usize) -> Vec<u8> {
+        let encoded = packet(PUBLISH, &vec![0; length]);
+        encoded[1..encoded.len() - length].to_vec()
+    }
+
+    fn publish(header: u8, topic: &str, packet_id: Option<u16>, payload: &str) -> Vec<u8> {
+        let mut body = encode_string(topic);
+        if let Some(id) = packet_id {
+            body.extend_from_slice(&[(id >> 8) as u8, id as u8]);
+        }
+        body.extend_from_slice(payload.as_bytes());
+        packet(header, &body)
+    }
+
+    fn gray(value: u8) -> RGB {
+        RGB {
+            red: value,
+            green: value,
+            blue: value,
+        }
+    }
+
+    fn cell_message(row: i32, column: i32, value: u8) -> String {
+        let color = format!(r#""red":{0},"green":{0},"blue":{0}"#, value);
+        format!(r#"{{"row":{},"column":{},{}}}"#, row, column, color)
+    }
+
+    fn cell_at(sharedgrid: &SharedGrid, row: usize, column: usize) -> RGB {
+        let mut layers = sharedgrid.sharedgrid.lock().unwrap();
+        lib::layers::layer_mut(&mut layers, &None).unwrap().grid[row][column]
+    }
+
+    #[test]
+    fn remaining_length_uses_7_bits_per_byte() {
+        assert_eq!(remaining_length(0), vec![0x00]);
+        assert_eq!(remaining_length(127), vec![0x7F]);
+        assert_eq!(remaining_length(128), vec![0x80, 0x01]);
+        assert_eq!(remaining_length(16_383), vec![0xFF, 0x7F]);
+        assert_eq!(remaining_length(16_384), vec![0x80, 0x80, 0x01]);
+        assert_eq!(remaining_length(2_097_152), vec![0x80, 0x80, 0x80, 0x01]);
+    }
+
+    #[test]
+    fn packets_are_read_back() {
+        for &length in [0, 1, 127, 128, 16_384].iter() {
+            let body: Vec<u8> = (0..length).map(|i| i as u8).collect();
+            let mut reader = Cursor::new(packet(CONNACK, &body));
+            assert_eq!(read_packet(&mut reader).unwrap(), (CONNACK, body));
+        }
+    }
+
+    #[test]
+    fn packets_follow_each_other() {
+        let mut stream = packet(PINGREQ, &[]);
+        stream.extend(packet(CONNACK, &[0, 0]));
+        let mut reader = Cursor::new(stream);
+        assert_eq!(read_packet(&mut reader).unwrap(), (PINGREQ, Vec::new()));
+        assert_eq!(read_packet(&mut reader).unwrap(), (CONNACK, vec![0, 0]));
+    }
+
+    #[test]
+    fn remaining_length_has_at_most_4_bytes() {
+        let mut reader = Cursor::new(vec![PUBLISH, 0xFF, 0xFF, 0xFF, 0xFF, 0x01]);
+        let error = read_packet(&mut reader).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid MQTT packet length");
+    }
+
+    #[test]
+    fn short_packets_are_errors() {
+        let short = |bytes: &[u8]| read_packet(&mut Cursor::new(bytes.to_vec())).unwrap_err();
+        assert_eq!(short(&[]).kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(short(&[PUBLISH]).kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(short(&[PUBLISH, 0x80]).kind(), ErrorKind::UnexpectedEof);
+        assert_eq!(short(&[PUBLISH, 3, 0, 1]).kind(), ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn strings_have_a_big_endian_length() {
+        assert_eq!(encode_string("MQTT"), vec![0, 4, b'M', b'Q', b'T', b'T']);
+        assert_eq!(encode_string(""), vec![0, 0]);
+        assert_eq!(&encode_string(&"a".repeat(300))[..2], &[0x01, 0x2C]);
+    }
+
+    #[test]
+    fn published_messages_are_drawn() {
+        let sharedgrid = lib::grid_init(4, 4, BLACK);
+        let shared_palettes = lib::palette::palettes_init();
+        let shared_events = lib::events::events_init();
+
+        let mut stream = publish(PUBLISH, "squares/cell", None, &cell_message(0, 1, 10));
+        //QoS 1, the packet id comes before the payload
+        stream.extend(publish(
+            PUBLISH | 0x02,
+            "squares/cell",
+            Some(7),
+            &cell_message(2, 3, 20),
+        ));
+        //not a publish, a topic longer than the packet and a topic that is not drawn
+        stream.extend(packet(PINGREQ, &[]));
+        stream.extend(packet(PUBLISH, &[0, 9, b'a']));
+        stream.extend(publish(
+            PUBLISH,
+            "squares/pixel",
+            None,
+            &cell_message(3, 3, 30),
+        ));
+
+        //the messages are read until the connection ends
+        let mut reader = Cursor::new(stream);
+        let result = read_messages(&mut reader, &sharedgrid, &shared_palettes, &shared_events);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::UnexpectedEof);
+
+        assert_eq!(cell_at(&sharedgrid, 0, 1), gray(10));
+        assert_eq!(cell_at(&sharedgrid, 2, 3), gray(20));
+        assert_eq!(cell_at(&sharedgrid, 3, 3), BLACK);
+    }
+
+    #[test]
+    fn messages_are_checked_like_requests() {
+        let sharedgrid = lib::grid_init(4, 4, BLACK);
+        let shared_palettes = lib::palette::palettes_init();
+        let shared_events = lib::events::events_init();
+        let apply = |topic, payload: &str| {
+            let payload = payload.as_bytes();
+            apply_message(
+                topic,
+                payload,
+                &sharedgrid,
+                &shared_palettes,
+                &shared_events,
+            )
+        };
+
+        assert!(apply("squares/cell", &cell_message(0, 0, 10)).is_ok());
+        assert!(apply("squares/cell", &cell_message(4, 0, 10)).is_err());
+        assert!(apply("squares/cell", "not json").is_err());
+        assert!(apply("squares/pixel", "{}").is_err());
+    }
+}
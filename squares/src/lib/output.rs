@@ -0,0 +1,358 @@
+use std::fs::OpenOptions;
+use std::io::{Error, ErrorKind, Write};
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::{thread, time};
+
+use crate::lib::data::{SharedGrid, RGB};
+use crate::lib::dmx::Layout;
+use crate::lib::layers;
+
+//hardware the grid is mirrored to
+#[derive(Clone, Debug)]
+pub enum Sink {
+    //an Open Pixel Control server, like fadecandy, as host:port
+    Opc(String),
+    //a serial device that speaks TPM2
+    Tpm2(PathBuf),
+    //a serial device running the Adalight sketch
+    Adalight(PathBuf),
+}
+
+//how the cells are wired up on the led matrix
+#[derive(Clone)]
+pub struct OutputMapping {
+    pub layout: Layout,
+    //clockwise in degrees, applied before the flips
+    pub rotation: u16,
+    pub flip_horizontal: bool,
+    pub flip_vertical: bool,
+    pub gamma: f32,
+}
+
+pub fn parse_sink(input: &str) -> std::io::Result<Sink> {
+    let mut parts = input.splitn(2, ':');
+
+    match (parts.next(), parts.next()) {
+        (Some("opc"), Some(address)) => Ok(Sink::Opc(String::from(address))),
+        (Some("tpm2"), Some(path)) => Ok(Sink::Tpm2(PathBuf::from(path))),
+        (Some("adalight"), Some(path)) => Ok(Sink::Adalight(PathBuf::from(path))),
+        _ => Err(Error::new(
+            ErrorKind::Other,
+            "Outputs are written as opc:<host>:<port>, tpm2:<device> or adalight:<device>",
+        )),
+    }
+}
+
+pub fn parse_rotation(input: &str) -> std::io::Result<u16> {
+    match input.parse::<u16>() {
+        Ok(rotation) if rotation % 90 == 0 && rotation < 360 => Ok(rotation),
+        _ => Err(Error::new(
+            ErrorKind::Other,
+            "Rotation must be 0, 90, 180 or 270",
+        )),
+    }
+}
+
+//8 bit corrected values for every 8 bit value, 1.0 leaves the colors as they are
+fn gamma_table(gamma: f32) -> Vec<u8> {
+    (0..256)
+        .map(|value| ((value as f32 / 255.0).powf(gamma) * 255.0).round() as u8)
+        .collect()
+}
+
+fn rotate(cells: &[Vec<RGB>], rotation: u16) -> Vec<Vec<RGB>> {
+    let rows = cells.len();
+    let columns = cells[0].len();
+
+    match rotation {
+        90 => (0..columns)
+            .map(|i| (0..rows).map(|j| cells[rows - 1 - j][i]).collect())
+            .collect(),
+        180 => (0..rows)
+            .map(|i| (0..columns).map(|j| cells[rows - 1 - i][columns - 1 - j]).collect())
+            .collect(),
+        270 => (0..columns)
+            .map(|i| (0..rows).map(|j| cells[j][columns - 1 - i]).collect())
+            .collect(),
+        _ => cells.to_vec(),
+    }
+}
+
+//the colors of all cells in the order of the leds, 3 bytes each
+pub fn frame(cells: &[Vec<RGB>], mapping: &OutputMapping, gamma: &[u8]) -> Vec<u8> {
+    let mut cells = rotate(cells, mapping.rotation);
+    if mapping.flip_horizontal {
+        for row in cells.iter_mut() {
+            row.reverse();
+        }
+    }
+    if mapping.flip_vertical {
+        cells.reverse();
+    }
+
+    let mut pixels = Vec::with_capacity(cells.len() * cells[0].len() * 3);
+    for (i, row) in cells.iter_mut().enumerate() {
+        if let Layout::Serpentine = mapping.layout {
+            if i % 2 == 1 {
+                row.reverse();
+            }
+        }
+        for color in row.iter() {
+            pixels.push(gamma[color.red as usize]);
+            pixels.push(gamma[color.green as usize]);
+            pixels.push(gamma[color.blue as usize]);
+        }
+    }
+    pixels
+}
+
+//channel 0, command 0 (set pixel colors) and the length of the data
+fn opc_message(pixels: &[u8]) -> Vec<u8> {
+    let length = pixels.len().min(0xFFFF);
+    let mut message = vec![0, 0, (length >> 8) as u8, length as u8];
+    message.extend_from_slice(&pixels[..length]);
+    message
+}
+
+//a data frame between the start bytes and the end byte
+fn tpm2_frame(pixels: &[u8]) -> Vec<u8> {
+    let length = pixels.len().min(0xFFFF);
+    let mut message = vec![0xC9, 0xDA, (length >> 8) as u8, length as u8];
+    message.extend_from_slice(&pixels[..length]);
+    message.push(0x36);
+    message
+}
+
+//"Ada", the number of leds minus one and a checksum of it
+fn adalight_frame(pixels: &[u8]) -> Vec<u8> {
+    let count = (pixels.len() / 3).max(1).min(0x10000) - 1;
+    let (high, low) = ((count >> 8) as u8, count as u8);
+    let mut message = vec![b'A', b'd', b'a', high, low, high ^ low ^ 0x55];
+    message.extend_from_slice(&pixels[..(count + 1) * 3]);
+    message
+}
+
+fn open(sink: &Sink) -> std::io::Result<Box<dyn Write + Send>> {
+    match sink {
+        Sink::Opc(address) => {
+            let stream = TcpStream::connect(address.as_str())?;
+            stream.set_nodelay(true)?;
+            Ok(Box::new(stream))
+        }
+        //the serial port has to be set up beforehand, e.g. with stty
+        Sink::Tpm2(path) | Sink::Adalight(path) => {
+            let file = OpenOptions::new().write(true).open(path)?;
+            Ok(Box::new(file))
+        }
+    }
+}
+
+//sends what is on screen to the sink every interval, lost connections are opened again
+pub fn run(sink: Sink, mapping: OutputMapping, interval: time::Duration, sharedgrid: SharedGrid) {
+    let gamma = gamma_table(mapping.gamma);
+    let mut writer: Option<Box<dyn Write + Send>> = None;
+    //errors are only printed once until the sink works again
+    let mut reported = false;
+
+    loop {
+        thread::sleep(interval);
+
+        if writer.is_none() {
+            match open(&sink) {
+                Ok(opened) => {
+                    println!("Output {:?} connected", sink);
+                    writer = Some(opened);
+                    reported = false;
+                }
+                Err(error) => {
+                    if !reported {
+                        println!("Output {:?} failed: {}", sink, error);
+                        reported = true;
+                    }
+                    continue;
+                }
+            }
+        }
+
+        let screen = {
+            let sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+            layers::composite(&sharedgrid_data)
+        };
+        let pixels = frame(&screen.grid, &mapping, &gamma);
+        let message = match sink {
+            Sink::Opc(_) => opc_message(&pixels),
+            Sink::Tpm2(_) => tpm2_frame(&pixels),
+            Sink::Adalight(_) => adalight_frame(&pixels),
+        };
+
+        if let Some(output) = &mut writer {
+            if let Err(error) = output.write_all(&message).and_then(|_| output.flush()) {
+                println!("Output {:?} failed: {}", sink, error);
+                writer = None;
+                reported = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cell(value: u8) -> RGB {
+        RGB {
+            red: value,
+            green: value,
+            blue: value,
+        }
+    }
+
+    //1 2 3
+    //4 5 6
+    fn cells() -> Vec<Vec<RGB>> {
+        vec![
+            vec![cell(1), cell(2), cell(3)],
+            vec![cell(4), cell(5), cell(6)],
+        ]
+    }
+
+    fn mapping(layout: Layout, rotation: u16) -> OutputMapping {
+        OutputMapping {
+            layout,
+            rotation,
+            flip_horizontal: false,
+            flip_vertical: false,
+            gamma: 1.0,
+        }
+    }
+
+    //the cells in the order of the leds, by the first channel of every led
+    fn order(mapping: &OutputMapping) -> Vec<u8> {
+        let pixels = frame(&cells(), mapping, &gamma_table(1.0));
+        pixels.chunks(3).map(|pixel| pixel[0]).collect()
+    }
+
+    #[test]
+    fn sinks_are_kind_and_address() {
+        match parse_sink("opc:localhost:7890").unwrap() {
+            Sink::Opc(address) => assert_eq!(address, "localhost:7890"),
+            sink => panic!("{:?}", sink),
+        }
+        match parse_sink("tpm2:/dev/ttyACM0").unwrap() {
+            Sink::Tpm2(path) => assert_eq!(path, PathBuf::from("/dev/ttyACM0")),
+            sink => panic!("{:?}", sink),
+        }
+        match parse_sink("adalight:/dev/ttyUSB0").unwrap() {
+            Sink::Adalight(path) => assert_eq!(path, PathBuf::from("/dev/ttyUSB0")),
+            sink => panic!("{:?}", sink),
+        }
+        assert!(parse_sink("opc").is_err());
+        assert!(parse_sink("dmx:/dev/ttyUSB0").is_err());
+        assert!(parse_sink("").is_err());
+    }
+
+    #[test]
+    fn rotation_is_a_quarter_turn() {
+        assert_eq!(parse_rotation("270").unwrap(), 270);
+        assert!(parse_rotation("45").is_err());
+        assert!(parse_rotation("360").is_err());
+        assert!(parse_rotation("-90").is_err());
+    }
+
+    #[test]
+    fn gamma_keeps_black_and_white() {
+        let table = gamma_table(2.2);
+        assert_eq!(table.len(), 256);
+        assert_eq!(table[0], 0);
+        assert_eq!(table[255], 255);
+        assert!(table[128] < 128);
+
+        let linear = gamma_table(1.0);
+        assert!((0..256).all(|value| linear[value] as usize == value));
+    }
+
+    #[test]
+    fn frames_follow_the_layout() {
+        let row_major = mapping(Layout::RowMajor, 0);
+        assert_eq!(frame(&cells(), &row_major, &gamma_table(1.0)).len(), 18);
+        assert_eq!(order(&row_major), vec![1, 2, 3, 4, 5, 6]);
+
+        let serpentine = mapping(Layout::Serpentine, 0);
+        assert_eq!(order(&serpentine), vec![1, 2, 3, 6, 5, 4]);
+    }
+
+    #[test]
+    fn frames_are_rotated_clockwise() {
+        let order_of = |rotation| order(&mapping(Layout::RowMajor, rotation));
+        assert_eq!(order_of(90), vec![4, 1, 5, 2, 6, 3]);
+        assert_eq!(order_of(180), vec![6, 5, 4, 3, 2, 1]);
+        assert_eq!(order_of(270), vec![3, 6, 2, 5, 1, 4]);
+    }
+
+    #[test]
+    fn frames_are_flipped_after_the_rotation() {
+        let mut flipped = mapping(Layout::RowMajor, 90);
+        flipped.flip_horizontal = true;
+        assert_eq!(order(&flipped), vec![1, 4, 2, 5, 3, 6]);
+
+        let mut flipped = mapping(Layout::RowMajor, 0);
+        flipped.flip_vertical = true;
+        assert_eq!(order(&flipped), vec![4, 5, 6, 1, 2, 3]);
+    }
+
+    #[test]
+    fn frames_are_gamma_corrected() {
+        let gamma = gamma_table(2.2);
+        let pixels = frame(&[vec![cell(128)]], &mapping(Layout::RowMajor, 0), &gamma);
+        assert_eq!(pixels, vec![gamma[128]; 3]);
+    }
+
+    #[test]
+    fn opc_header_is_channel_command_and_length() {
+        let message = opc_message(&[1, 2, 3, 4, 5, 6]);
+        assert_eq!(message, vec![0, 0, 0, 6, 1, 2, 3, 4, 5, 6]);
+
+        assert_eq!(opc_message(&[]), vec![0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn opc_data_is_cut_at_the_largest_length() {
+        let message = opc_message(&vec![7; 0x10002]);
+        assert_eq!(&message[..4], &[0, 0, 0xFF, 0xFF]);
+        assert_eq!(message.len(), 4 + 0xFFFF);
+    }
+
+    #[test]
+    fn tpm2_frame_has_start_and_end_bytes() {
+        let message = tpm2_frame(&[1, 2, 3]);
+        assert_eq!(message, vec![0xC9, 0xDA, 0, 3, 1, 2, 3, 0x36]);
+
+        let message = tpm2_frame(&vec![7; 0x10002]);
+        assert_eq!(&message[..4], &[0xC9, 0xDA, 0xFF, 0xFF]);
+        assert_eq!(message.len(), 5 + 0xFFFF);
+        assert_eq!(message[message.len() - 1], 0x36);
+    }
+
+    #[test]
+    fn adalight_header_counts_leds_minus_one() {
+        let message = adalight_frame(&[1, 2, 3, 4, 5, 6]);
+        assert_eq!(&message[..6], &[b'A', b'd', b'a', 0, 1, 0x54]);
+        assert_eq!(&message[6..], &[1, 2, 3, 4, 5, 6]);
+
+        //300 leds, 299 is 0x012B
+        let message = adalight_frame(&vec![7; 900]);
+        assert_eq!(&message[3..6], &[0x01, 0x2B, 0x01 ^ 0x2B ^ 0x55]);
+        assert_eq!(message.len(), 6 + 900);
+    }
+
+    #[test]
+    fn adalight_leaves_out_incomplete_and_too_many_leds() {
+        let message = adalight_frame(&[1, 2, 3, 4]);
+        assert_eq!(message, vec![b'A', b'd', b'a', 0, 0, 0x55, 1, 2, 3]);
+
+        let message = adalight_frame(&vec![7; 3 * 0x10001]);
+        assert_eq!(&message[3..6], &[0xFF, 0xFF, 0x55]);
+        assert_eq!(message.len(), 6 + 3 * 0x10000);
+    }
+}
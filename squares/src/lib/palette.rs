@@ -0,0 +1,197 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+use crate::lib::api::Color;
+use crate::lib::blend::BlendMode;
+use crate::lib::color;
+use crate::lib::data::{Grid, Layers, RGB};
+
+pub struct Palette {
+    pub name: String,
+    pub colors: Vec<RGB>,
+}
+
+pub struct Palettes {
+    pub palettes: Vec<Palette>,
+    pub active: usize,
+}
+
+pub struct SharedPalettes {
+    pub palettes: Arc<Mutex<Palettes>>,
+}
+
+//starts with the 16 CGA colors as "default"
+pub fn palettes_init() -> SharedPalettes {
+    let cga = [
+        0x000000, 0x0000AA, 0x00AA00, 0x00AAAA, 0xAA0000, 0xAA00AA, 0xAA5500, 0xAAAAAA, 0x555555,
+        0x5555FF, 0x55FF55, 0x55FFFF, 0xFF5555, 0xFF55FF, 0xFFFF55, 0xFFFFFF,
+    ];
+    let palette = Palette {
+        name: String::from("default"),
+        colors: cga.iter().map(|value| color::from_u32(*value)).collect(),
+    };
+
+    SharedPalettes {
+        palettes: Arc::new(Mutex::new(Palettes {
+            palettes: vec![palette],
+            active: 0,
+        })),
+    }
+}
+
+//a copy of the colors of the active palette, so the palette lock is not held while drawing
+pub fn active_colors(shared_palettes: &SharedPalettes) -> Vec<RGB> {
+    let palettes = shared_palettes
+        .palettes
+        .lock()
+        .expect("palette lock failed");
+    palettes.palettes[palettes.active].colors.clone()
+}
+
+pub fn color_at(colors: &[RGB], index: u8) -> std::io::Result<RGB> {
+    match colors.get(index as usize) {
+        Some(color) => Ok(*color),
+        None => Err(Error::new(ErrorKind::Other, "Palette index out of range")),
+    }
+}
+
+//adds a palette or replaces the colors of a palette with the same name
+pub fn add_palette(palettes: &mut Palettes, palette: Palette) -> std::io::Result<()> {
+    if palette.colors.is_empty() || palette.colors.len() > 256 {
+        return Err(Error::new(
+            ErrorKind::Other,
+            "A palette must have between 1 and 256 colors",
+        ));
+    }
+
+    match palettes
+        .palettes
+        .iter_mut()
+        .find(|existing| existing.name == palette.name)
+    {
+        Some(existing) => existing.colors = palette.colors,
+        None => palettes.palettes.push(palette),
+    }
+    Ok(())
+}
+
+//the palette index of a color, if it is drawn exactly in the palette color
+pub fn exact_index(color: &Color, alpha: u8, mode: &BlendMode) -> Option<u8> {
+    match mode {
+        BlendMode::Normal if alpha == 255 => color::palette_index(color),
+        _ => None,
+    }
+}
+
+//remembers which palette index a cell was drawn with, None for any other color
+pub fn mark(grid: &mut Grid, row: usize, column: usize, index: Option<u8>) {
+    grid.indices[row][column] = index;
+}
+
+//gives every cell drawn with a palette index the color of that index in the new palette.
+//Cells that were painted over since then no longer have the old palette color and are skipped.
+pub fn recolor(layers: &mut Layers, old: &[RGB], new: &[RGB]) {
+    for layer in layers.layers.iter_mut() {
+        let grid = &mut layer.grid;
+        for row in 0..grid.grid.len() {
+            for column in 0..grid.grid[row].len() {
+                if let Some(index) = grid.indices[row][column] {
+                    let index = index as usize;
+                    if index < old.len()
+                        && grid.grid[row][column] == old[index]
+                        && index < new.len()
+                    {
+                        grid.grid[row][column] = new[index];
+                    } else {
+                        grid.indices[row][column] = None;
+                    }
+                }
+            }
+        }
+    }
+}
+
+//loads a GIMP palette (.gpl) or a list of hex colors (.hex), named after the file
+pub fn load_palette(path: &Path) -> std::io::Result<Palette> {
+    let contents = fs::read_to_string(path)?;
+    let name = match path.file_stem() {
+        Some(stem) => stem.to_string_lossy().into_owned(),
+        None => String::from("file"),
+    };
+
+    let colors = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("gpl") => parse_gpl(&contents)?,
+        Some("hex") => parse_hex_list(&contents)?,
+        _ => {
+            return Err(Error::new(
+                ErrorKind::Other,
+                "Palette files must end in .gpl or .hex",
+            ))
+        }
+    };
+
+    Ok(Palette { name, colors })
+}
+
+fn parse_gpl(contents: &str) -> std::io::Result<Vec<RGB>> {
+    let mut lines = contents.lines();
+    if lines.next().map(|line| line.trim()) != Some("GIMP Palette") {
+        return Err(Error::new(
+            ErrorKind::Other,
+            "GIMP palettes must start with \"GIMP Palette\"",
+        ));
+    }
+
+    let mut colors = Vec::new();
+    for line in lines {
+        let line = line.trim();
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("Name:")
+            || line.starts_with("Columns:")
+        {
+            continue;
+        }
+
+        let values: Vec<u8> = line
+            .split_whitespace()
+            .take(3)
+            .map(|value| value.parse::<u8>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| Error::new(ErrorKind::Other, format!("Invalid palette line: {}", line)))?;
+        if values.len() != 3 {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("Invalid palette line: {}", line),
+            ));
+        }
+        colors.push(RGB {
+            red: values[0],
+            green: values[1],
+            blue: values[2],
+        });
+    }
+    Ok(colors)
+}
+
+fn parse_hex_list(contents: &str) -> std::io::Result<Vec<RGB>> {
+    let mut colors = Vec::new();
+    for line in contents.lines() {
+        let line = line.trim().trim_start_matches('#');
+        if line.is_empty() {
+            continue;
+        }
+
+        if line.len() != 6 {
+            return Err(Error::new(
+                ErrorKind::Other,
+                format!("Invalid hex color: {}", line),
+            ));
+        }
+        let (rgb, _alpha) = color::parse_color(&format!("#{}", line))?;
+        colors.push(rgb);
+    }
+    Ok(colors)
+}
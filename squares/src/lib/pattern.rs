@@ -0,0 +1,197 @@
+use std::io::{Error, ErrorKind};
+
+use crate::lib::color::hsv_to_rgb;
+use crate::lib::data::{Grid, RGB};
+
+pub enum Pattern {
+    Solid,
+    Checkerboard,
+    HorizontalGradient,
+    VerticalGradient,
+    RadialGradient,
+    Stripes,
+    Noise,
+    Plasma,
+    Rainbow,
+    TestCard,
+}
+
+//color: the pattern color, second_color: the color between or behind the pattern,
+//size: width of stripes and squares or the scale of noise and plasma, seed: for noise
+pub struct PatternParams {
+    pub color: RGB,
+    pub second_color: RGB,
+    pub size: usize,
+    pub seed: u32,
+}
+
+pub fn pattern_from_name(name: &str) -> std::io::Result<Pattern> {
+    match name {
+        "solid" => Ok(Pattern::Solid),
+        "checkerboard" => Ok(Pattern::Checkerboard),
+        "gradient" | "horizontal_gradient" => Ok(Pattern::HorizontalGradient),
+        "vertical_gradient" => Ok(Pattern::VerticalGradient),
+        "radial_gradient" => Ok(Pattern::RadialGradient),
+        "stripes" => Ok(Pattern::Stripes),
+        "noise" => Ok(Pattern::Noise),
+        "plasma" => Ok(Pattern::Plasma),
+        "rainbow" => Ok(Pattern::Rainbow),
+        "test_card" => Ok(Pattern::TestCard),
+        _ => Err(Error::new(ErrorKind::Other, "Unknown pattern")),
+    }
+}
+
+//white pattern on the background of the grid
+pub fn default_params(grid: &Grid) -> PatternParams {
+    PatternParams {
+        color: RGB {
+            red: 255,
+            green: 255,
+            blue: 255,
+        },
+        second_color: grid.background,
+        size: 1,
+        seed: 1,
+    }
+}
+
+//fills the whole grid with a pattern
+pub fn fill_grid(grid: &mut Grid, pattern: &Pattern, params: &PatternParams) {
+    let max_rows = grid.grid.len();
+    let max_columns = grid.grid[0].len();
+    let size = if params.size == 0 { 1 } else { params.size };
+
+    for row in 0..max_rows {
+        for column in 0..max_columns {
+            grid.grid[row][column] = match pattern {
+                Pattern::Solid => params.color,
+                Pattern::Checkerboard => {
+                    if (row / size + column / size) % 2 == 0 {
+                        params.color
+                    } else {
+                        params.second_color
+                    }
+                }
+                Pattern::HorizontalGradient => {
+                    mix(params.second_color, params.color, column, max_columns)
+                }
+                Pattern::VerticalGradient => mix(params.second_color, params.color, row, max_rows),
+                Pattern::RadialGradient => {
+                    radial_gradient(row, column, max_rows, max_columns, params)
+                }
+                Pattern::Stripes => {
+                    if (row / size) % 2 == 0 {
+                        params.color
+                    } else {
+                        params.second_color
+                    }
+                }
+                Pattern::Noise => {
+                    let value = noise(row / size, column / size, params.seed);
+                    mix(params.second_color, params.color, value as usize, 256)
+                }
+                Pattern::Plasma => plasma(row, column, size),
+                Pattern::Rainbow => {
+                    hsv_to_rgb(360.0 * column as f64 / max_columns as f64, 1.0, 1.0)
+                }
+                Pattern::TestCard => test_card(row, column, max_rows, max_columns),
+            };
+            grid.opaque[row][column] = true;
+        }
+    }
+}
+
+//linear interpolation between two colors, step out of steps
+pub fn mix(from: RGB, to: RGB, step: usize, steps: usize) -> RGB {
+    if steps < 2 {
+        return to;
+    }
+    let channel =
+        |a: u8, b: u8| (a as i32 + (b as i32 - a as i32) * step as i32 / (steps - 1) as i32) as u8;
+
+    RGB {
+        red: channel(from.red, to.red),
+        green: channel(from.green, to.green),
+        blue: channel(from.blue, to.blue),
+    }
+}
+
+//color in the center, second color at the corners
+fn radial_gradient(
+    row: usize,
+    column: usize,
+    max_rows: usize,
+    max_columns: usize,
+    params: &PatternParams,
+) -> RGB {
+    let center_row = (max_rows as f64 - 1.0) / 2.0;
+    let center_column = (max_columns as f64 - 1.0) / 2.0;
+    let max_distance = (center_row * center_row + center_column * center_column).sqrt();
+
+    let d_row = row as f64 - center_row;
+    let d_column = column as f64 - center_column;
+    let distance = (d_row * d_row + d_column * d_column).sqrt();
+
+    let step = if max_distance > 0.0 {
+        (255.0 * distance / max_distance) as usize
+    } else {
+        0
+    };
+    mix(params.color, params.second_color, step, 256)
+}
+
+//deterministic pseudo random value between 0 and 255 for a position and a seed
+fn noise(row: usize, column: usize, seed: u32) -> u8 {
+    let mut x = seed
+        .wrapping_mul(0x9E37_79B9)
+        .wrapping_add((row as u32).wrapping_mul(0x85EB_CA6B))
+        .wrapping_add((column as u32).wrapping_mul(0xC2B2_AE35));
+    x ^= x >> 16;
+    x = x.wrapping_mul(0x7FEB_352D);
+    x ^= x >> 15;
+    x = x.wrapping_mul(0x846C_A68B);
+    x ^= x >> 16;
+    (x & 0xFF) as u8
+}
+
+fn plasma(row: usize, column: usize, size: usize) -> RGB {
+    let scale = size as f64 + 1.0;
+    let x = column as f64 / scale;
+    let y = row as f64 / scale;
+
+    let value = x.sin() + y.sin() + ((x + y) / 2.0).sin() + (x * x + y * y).sqrt().sin();
+    hsv_to_rgb(value * 45.0, 1.0, 1.0)
+}
+
+//color bars on top, a grey ramp at the bottom
+fn test_card(row: usize, column: usize, max_rows: usize, max_columns: usize) -> RGB {
+    let bars = [
+        (192, 192, 192),
+        (192, 192, 0),
+        (0, 192, 192),
+        (0, 192, 0),
+        (192, 0, 192),
+        (192, 0, 0),
+        (0, 0, 192),
+    ];
+
+    if row < max_rows - max_rows / 4 || max_rows < 4 {
+        let (red, green, blue) = bars[column * bars.len() / max_columns];
+        RGB { red, green, blue }
+    } else {
+        mix(
+            RGB {
+                red: 0,
+                green: 0,
+                blue: 0,
+            },
+            RGB {
+                red: 255,
+                green: 255,
+                blue: 255,
+            },
+            column,
+            max_columns,
+        )
+    }
+}
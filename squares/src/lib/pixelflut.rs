@@ -0,0 +1,187 @@
+use std::io::{Error, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use crate::lib::blend::{self, BlendMode};
+use crate::lib::data::{Grid, SharedGrid};
+use crate::lib::{color, layers, palette};
+
+//a connection that sends this much without a line break is closed
+const MAX_LINE: usize = 1024;
+
+const HELP: &str = "HELP\nPX <x> <y> <rrggbb or rrggbbaa>\nPX <x> <y>\nSIZE\n";
+
+enum Command {
+    SetPixel {
+        x: usize,
+        y: usize,
+        color: u32,
+        alpha: u8,
+    },
+    GetPixel {
+        x: usize,
+        y: usize,
+    },
+    Size,
+    Help,
+}
+
+//accepts clients until the listener fails, every client gets its own thread
+pub fn serve(port: u16, sharedgrid: SharedGrid) -> std::io::Result<()> {
+    let listener = TcpListener::bind(("0.0.0.0", port))?;
+    println!("Pixelflut server on port {}", port);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let sharedgrid_client = SharedGrid {
+            sharedgrid: sharedgrid.sharedgrid.clone(),
+        };
+        thread::spawn(move || {
+            //a client that misbehaves only loses its own connection
+            let _ = handle_client(stream, &sharedgrid_client);
+        });
+    }
+    Ok(())
+}
+
+//handles everything that arrived with one read at once, so the grid is locked once per read
+//and not once per line
+fn handle_client(mut stream: TcpStream, sharedgrid: &SharedGrid) -> std::io::Result<()> {
+    let mut buffer = vec![0; 64 * 1024];
+    let mut pending: Vec<u8> = Vec::new();
+
+    loop {
+        let length = stream.read(&mut buffer)?;
+        if length == 0 {
+            return Ok(());
+        }
+        pending.extend_from_slice(&buffer[..length]);
+
+        //only complete lines are handled, the rest waits for the next read
+        let end = match pending.iter().rposition(|byte| *byte == b'\n') {
+            Some(position) => position + 1,
+            None if pending.len() > MAX_LINE => {
+                return Err(Error::new(ErrorKind::Other, "Line is too long"));
+            }
+            None => continue,
+        };
+        let lines: Vec<u8> = pending.drain(..end).collect();
+
+        let answer = handle_lines(&String::from_utf8_lossy(&lines), sharedgrid);
+        if !answer.is_empty() {
+            stream.write_all(answer.as_bytes())?;
+        }
+    }
+}
+
+//the answer is only sent after the grid is unlocked, so slow clients don't block the grid
+fn handle_lines(lines: &str, sharedgrid: &SharedGrid) -> String {
+    let mut answer = String::new();
+    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+    //read-back returns what is on screen, merged only once and only if a client asks
+    let mut screen: Option<Grid> = None;
+
+    for line in lines.lines() {
+        let command = match parse_command(line) {
+            Ok(command) => command,
+            Err(error) => {
+                answer.push_str(&format!("ERROR {}\n", error));
+                continue;
+            }
+        };
+
+        match command {
+            Command::SetPixel { x, y, color, alpha } => {
+                let grid_data = layers::layer_mut(&mut sharedgrid_data, &None)
+                    .expect("content layer exists");
+                if y < grid_data.grid.len() && x < grid_data.grid[0].len() {
+                    let color = color::from_u32(color);
+                    blend::paint(grid_data, y, x, color, alpha, &BlendMode::Normal);
+                    palette::mark(grid_data, y, x, None);
+                    screen = None;
+                }
+            }
+            Command::GetPixel { x, y } => {
+                if screen.is_none() {
+                    screen = Some(layers::composite(&sharedgrid_data));
+                }
+                if let Some(screen) = &screen {
+                    if y < screen.grid.len() && x < screen.grid[0].len() {
+                        let color = screen.grid[y][x];
+                        answer.push_str(&format!(
+                            "PX {} {} {:02x}{:02x}{:02x}\n",
+                            x, y, color.red, color.green, color.blue
+                        ));
+                    }
+                }
+            }
+            Command::Size => {
+                let rows = sharedgrid_data.layers[0].grid.grid.len();
+                let columns = sharedgrid_data.layers[0].grid.grid[0].len();
+                answer.push_str(&format!("SIZE {} {}\n", columns, rows));
+            }
+            Command::Help => answer.push_str(HELP),
+        }
+    }
+    answer
+}
+
+//x is the column and y the row of a cell
+fn parse_command(line: &str) -> std::io::Result<Command> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+
+    match words.as_slice() {
+        ["PX", x, y] => Ok(Command::GetPixel {
+            x: parse_number(x)?,
+            y: parse_number(y)?,
+        }),
+        ["PX", x, y, hex] => {
+            //from_str_radix takes a leading + as well, so the digits are checked first
+            if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(Error::new(ErrorKind::Other, "Invalid color"));
+            }
+            let value = match u32::from_str_radix(hex, 16) {
+                Ok(value) => value,
+                Err(_) => return Err(Error::new(ErrorKind::Other, "Invalid color")),
+            };
+            let (color, alpha) = match hex.len() {
+                6 => (value, 255),
+                8 => (value >> 8, (value & 0xFF) as u8),
+                _ => return Err(Error::new(ErrorKind::Other, "Invalid color")),
+            };
+            Ok(Command::SetPixel {
+                x: parse_number(x)?,
+                y: parse_number(y)?,
+                color,
+                alpha,
+            })
+        }
+        ["SIZE"] => Ok(Command::Size),
+        ["HELP"] => Ok(Command::Help),
+        _ => Err(Error::new(ErrorKind::Other, "Unknown command")),
+    }
+}
+
+fn parse_number(word: &str) -> std::io::Result<usize> {
+    match word.parse::<usize>() {
+        Ok(number) => Ok(number),
+        Err(_) => Err(Error::new(ErrorKind::Other, "Invalid coordinate")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_are_hex_digits_only() {
+        assert!(parse_command("PX 1 2 ff8800").is_ok());
+        assert!(parse_command("PX 1 2 ff880080").is_ok());
+        assert!(parse_command("PX 1 2 +fffff").is_err());
+        assert!(parse_command("PX 1 2 +ffffff0").is_err());
+        assert!(parse_command("PX 1 2 ff88zz").is_err());
+    }
+}
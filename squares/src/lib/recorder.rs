@@ -0,0 +1,56 @@
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+use std::time::Instant;
+
+use crate::lib::events;
+
+//one line of a recording, replayed by squares_test's replay as a POST of body to path
+#[derive(Serialize)]
+struct Recorded<'a> {
+    //milliseconds since the recording started
+    time: u64,
+    path: String,
+    body: &'a serde_json::Value,
+}
+
+//writes every accepted drawing to the file until the program ends. An existing recording is
+//continued, the receiver is subscribed before the thread starts so no early drawing is missed.
+pub fn record(path: &Path, receiver: Receiver<events::Event>) -> std::io::Result<()> {
+    let file = OpenOptions::new().append(true).create(true).open(path)?;
+    let mut writer = BufWriter::new(file);
+    let start = Instant::now();
+
+    for event in receiver.iter() {
+        if !is_replayable(&event) {
+            continue;
+        }
+
+        let elapsed = start.elapsed();
+        let recorded = Recorded {
+            time: elapsed.as_secs() * 1000 + u64::from(elapsed.subsec_millis()),
+            path: format!("/{}", event.kind),
+            body: &event.data,
+        };
+        serde_json::to_writer(&mut writer, &recorded)?;
+        writer.write_all(b"\n")?;
+        writer.flush()?;
+    }
+    Ok(())
+}
+
+//binary grids only tell where they went, their pixels can not be played back
+fn is_replayable(event: &events::Event) -> bool {
+    match event.kind.as_str() {
+        "grid" => {
+            event
+                .data
+                .get("api_grid")
+                .map_or(false, |grid| !grid.is_null())
+                || event.data.get("data").map_or(false, |data| !data.is_null())
+        }
+        "cell" | "line" | "clear" | "pattern" | "text" => true,
+        _ => false,
+    }
+}
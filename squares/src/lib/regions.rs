@@ -0,0 +1,268 @@
+use std::io::{Error, ErrorKind};
+use std::sync::{Arc, Mutex};
+
+use crate::lib::auth::{Client, Scope};
+use crate::lib::data::Grid;
+use crate::lib::err;
+
+//a rectangle of the grid that belongs to a token
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Region {
+    pub owner: String,
+    pub row: i32,
+    pub column: i32,
+    pub rows: i32,
+    pub columns: i32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum RegionMode {
+    //writes that touch cells outside of the client's area are not drawn at all
+    Reject,
+    //only the cells inside the client's area are drawn
+    Clip,
+}
+
+pub struct Regions {
+    pub regions: Vec<Region>,
+    pub mode: RegionMode,
+}
+
+pub struct SharedRegions {
+    pub regions: Arc<Mutex<Regions>>,
+}
+
+//the cells a client may draw. A client that owns regions may only draw inside them,
+//everyone else may draw everywhere except in the regions of others. Admins draw everywhere.
+pub struct Area {
+    everywhere: bool,
+    own: Vec<Region>,
+    others: Vec<Region>,
+    pub mode: RegionMode,
+}
+
+pub fn regions_init(mode: RegionMode) -> SharedRegions {
+    SharedRegions {
+        regions: Arc::new(Mutex::new(Regions {
+            regions: Vec::new(),
+            mode,
+        })),
+    }
+}
+
+pub fn region_mode_from_name(name: &str) -> std::io::Result<RegionMode> {
+    match name {
+        "reject" => Ok(RegionMode::Reject),
+        "clip" => Ok(RegionMode::Clip),
+        _ => Err(Error::new(ErrorKind::Other, "Unknown region mode")),
+    }
+}
+
+//for writes that don't come from a client with a token, like udp or mqtt
+pub fn everywhere() -> Area {
+    Area {
+        everywhere: true,
+        own: Vec::new(),
+        others: Vec::new(),
+        mode: RegionMode::Clip,
+    }
+}
+
+//a copy of the regions at the time of the request, so the regions are not locked while drawing
+pub fn area(shared_regions: &SharedRegions, client: &Client) -> Area {
+    if client.scope == Scope::Admin {
+        return everywhere();
+    }
+
+    let regions = shared_regions.regions.lock().expect("regions lock failed");
+    let (own, others) = regions
+        .regions
+        .iter()
+        .cloned()
+        .partition(|region| region.owner == client.name);
+
+    Area {
+        everywhere: false,
+        own,
+        others,
+        mode: regions.mode,
+    }
+}
+
+//the ends are added up as i64, a region near i32::MAX would overflow otherwise
+fn region_contains(region: &Region, row: i32, column: i32) -> bool {
+    row >= region.row
+        && i64::from(row) < i64::from(region.row) + i64::from(region.rows)
+        && column >= region.column
+        && i64::from(column) < i64::from(region.column) + i64::from(region.columns)
+}
+
+//true if at least one cell of the region is on the grid
+pub fn on_grid(region: &Region, max_rows: usize, max_columns: usize) -> bool {
+    let row_end = i64::from(region.row) + i64::from(region.rows);
+    let column_end = i64::from(region.column) + i64::from(region.columns);
+    region.rows > 0
+        && region.columns > 0
+        && row_end > 0
+        && column_end > 0
+        && i64::from(region.row) < max_rows as i64
+        && i64::from(region.column) < max_columns as i64
+}
+
+pub fn contains(area: &Area, row: i32, column: i32) -> bool {
+    if area.everywhere {
+        return true;
+    }
+    if !area.own.is_empty() {
+        return area
+            .own
+            .iter()
+            .any(|region| region_contains(region, row, column));
+    }
+    !area
+        .others
+        .iter()
+        .any(|region| region_contains(region, row, column))
+}
+
+//true if the client may not draw on every cell
+pub fn is_limited(area: &Area) -> bool {
+    !area.everywhere && !(area.own.is_empty() && area.others.is_empty())
+}
+
+//true if the client may only draw inside its own regions
+pub fn owns_regions(area: &Area) -> bool {
+    !area.everywhere && !area.own.is_empty()
+}
+
+//in reject mode, fails if any of the cells that are on the grid is outside of the area
+pub fn check_cells<I: Iterator<Item = (i32, i32)>>(
+    area: &Area,
+    cells: I,
+    max_rows: usize,
+    max_columns: usize,
+) -> std::io::Result<()> {
+    if let RegionMode::Clip = area.mode {
+        return Ok(());
+    }
+
+    for (row, column) in cells {
+        let on_grid =
+            row >= 0 && row < max_rows as i32 && column >= 0 && column < max_columns as i32;
+        if on_grid && !contains(area, row, column) {
+            return Err(err::rejection("outside_region", "Outside of your region"));
+        }
+    }
+    Ok(())
+}
+
+//for writes that change many cells at once, like patterns and text. They are drawn on a copy
+//of the grid and only the cells inside the area are taken over.
+pub fn draw_masked<F: FnOnce(&mut Grid)>(
+    grid: &mut Grid,
+    area: &Area,
+    draw: F,
+) -> std::io::Result<()> {
+    if !is_limited(area) {
+        draw(grid);
+        return Ok(());
+    }
+
+    let mut copy = grid.clone();
+    draw(&mut copy);
+
+    let changed: Vec<(i32, i32)> = (0..grid.grid.len())
+        .flat_map(|row| (0..grid.grid[row].len()).map(move |column| (row, column)))
+        .filter(|&(row, column)| {
+            copy.grid[row][column] != grid.grid[row][column]
+                || copy.indices[row][column] != grid.indices[row][column]
+                || copy.opaque[row][column] != grid.opaque[row][column]
+        })
+        .map(|(row, column)| (row as i32, column as i32))
+        .collect();
+    check_cells(
+        area,
+        changed.iter().cloned(),
+        grid.grid.len(),
+        grid.grid[0].len(),
+    )?;
+
+    for (row, column) in changed {
+        if contains(area, row, column) {
+            let (row, column) = (row as usize, column as usize);
+            grid.grid[row][column] = copy.grid[row][column];
+            grid.indices[row][column] = copy.indices[row][column];
+            grid.opaque[row][column] = copy.opaque[row][column];
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(row: i32, column: i32, rows: i32, columns: i32) -> Region {
+        Region {
+            owner: String::from("visuals"),
+            row,
+            column,
+            rows,
+            columns,
+        }
+    }
+
+    #[test]
+    fn regions_at_the_end_of_the_range_do_not_overflow() {
+        let far = region(
+            i32::max_value() - 1,
+            i32::max_value() - 1,
+            i32::max_value(),
+            i32::max_value(),
+        );
+        assert!(region_contains(&far, i32::max_value(), i32::max_value()));
+        assert!(!region_contains(&far, 0, 0));
+    }
+
+    #[test]
+    fn owners_draw_only_inside_their_regions() {
+        let shared_regions = regions_init(RegionMode::Reject);
+        shared_regions
+            .regions
+            .lock()
+            .unwrap()
+            .regions
+            .push(region(2, 2, 3, 3));
+        let client = Client {
+            name: String::from("visuals"),
+            scope: Scope::Draw,
+        };
+        let area = area(&shared_regions, &client);
+        assert!(contains(&area, 2, 4));
+        assert!(!contains(&area, 5, 2));
+        assert!(!contains(&area, 2, 5));
+        assert!(owns_regions(&area));
+
+        let other = Client {
+            name: String::from("lights"),
+            scope: Scope::Draw,
+        };
+        assert!(!owns_regions(&super::area(&shared_regions, &other)));
+    }
+
+    #[test]
+    fn regions_need_a_cell_on_the_grid() {
+        assert!(on_grid(&region(0, 0, 8, 16), 8, 16));
+        assert!(on_grid(&region(-4, -4, 5, 5), 8, 16));
+        assert!(on_grid(
+            &region(7, 15, i32::max_value(), i32::max_value()),
+            8,
+            16
+        ));
+        assert!(!on_grid(&region(8, 0, 1, 1), 8, 16));
+        assert!(!on_grid(&region(0, 16, 1, 1), 8, 16));
+        assert!(!on_grid(&region(-4, 0, 4, 1), 8, 16));
+        assert!(!on_grid(&region(i32::min_value(), 0, 1, 1), 8, 16));
+        assert!(!on_grid(&region(0, 0, 0, 1), 8, 16));
+    }
+}
@@ -1,98 +1,179 @@
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::{thread, time};
+use std::time;
 
-use rocket::State;
+use rocket::http::{ContentType, Status};
+use rocket::response::content::Content;
+use rocket::{Data, State};
 use rocket_contrib::json;
 use rocket_contrib::json::{Json, JsonValue};
 
 use crate::lib;
-use lib::api::{Cell, Line, ApiGrid};
-use lib::data::{SharedGrid, RGB};
+use lib::api::{
+    ApiAutomaton, ApiGrid, ApiIntervention, ApiLayer, ApiPalette, ApiPaletteCycle, ApiPaletteFile,
+    ApiPattern, ApiText, Cell, Clear, Line,
+};
+use lib::audit::{Rectangle, SharedAudit};
+use lib::auth::{AdminAccess, Client, DrawAccess, ReadAccess, Scope};
+use lib::automata::SharedAutomaton;
+use lib::data::{Grid, SharedGrid, RGB};
+use lib::events::SharedEvents;
+use lib::intervention::SharedIntervention;
+use lib::limits::SharedLimits;
+use lib::metrics::SharedMetrics;
+use lib::palette::{Palette, SharedPalettes};
+use lib::regions::{Region, SharedRegions};
+use lib::schedule::SharedSchedule;
+use lib::shutdown::SharedShutdown;
+use lib::text::SharedMarquee;
+use lib::timelapse::SharedTimelapse;
 
 
 //get grid information via http, push entire or partial grids
-#[post("/", data = "<grid>")]
-pub fn new_grid(grid: Json<ApiGrid>, sharedgrid: State<SharedGrid>) -> JsonValue {
-    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
-    let max_rows = &sharedgrid_data.grid.len();
-    let max_columns = &sharedgrid_data.grid[0].len();
-
-    let api_grid = &grid.api_grid;
+#[post("/", data = "<grid>", rank = 2)]
+pub fn new_grid(
+    grid: Json<ApiGrid>,
+    sharedgrid: State<SharedGrid>,
+    shared_palettes: State<SharedPalettes>,
+    events: State<SharedEvents>,
+    limits: State<SharedLimits>,
+    regions: State<SharedRegions>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: DrawAccess,
+) -> Result<JsonValue, Status> {
+    check_cells(&limits, &access.0, lib::draw::grid_cells(&grid))?;
 
-    for (i, row) in api_grid.iter().enumerate() {
-        for (j, _column) in row.iter().enumerate() {
+    let palette = lib::palette::active_colors(&shared_palettes);
+    let area = lib::regions::area(&regions, &access.0);
 
-            let cell = Cell {
-                row: i as i32 + grid.zero_row ,
-                column: j as i32 + grid.zero_column,
-                red: api_grid[i][j].red,
-                green: api_grid[i][j].green,
-                blue: api_grid[i][j].blue,
+    match lib::draw::grid(&grid, &palette, &area, &sharedgrid) {
+        Ok(()) => {
+            lib::events::publish(&events, "grid", "http", &*grid);
+            let (rows, columns) = match (&grid.data, grid.width) {
+                (Some(_), Some(width)) if width > 0 => {
+                    ((lib::draw::grid_cells(&grid) + width - 1) / width, width)
+                }
+                _ => (8, 8),
             };
+            let (row, column) = (grid.zero_row, grid.zero_column);
+            let cells = lib::audit::rectangle(row, column, rows as i32, columns as i32);
+            lib::audit::record(&audit, &access.0, "grid", vec![cells]);
+            Ok(json!("success"))
+        }
 
-            let values = lib::err::is_cell_value_in_range(&cell, max_rows, max_columns);
-            match values {
-                Ok(()) => {
-                    let color_arr = RGB {
-                        red: cell.red,
-                        green: cell.green,
-                        blue: cell.blue,
-                    };
-
-                    sharedgrid_data.grid[(cell.row) as usize][(cell.column) as usize] = color_arr;
-                    json!("success");
-                }
+        Err(error) => {
+            Ok(rejected(&metrics, error))
+        }
+    }
+}
 
-                Err(error) => {
-                    let response = error.to_string();
-                    // println!("{}", &response);
-                    json!(response);
-                }
-            }
+
+//raw rgb24 rows behind an 8 byte header, so clients with a high frame rate skip json
+#[post("/?<layer>", format = "application/octet-stream", data = "<data>", rank = 1)]
+pub fn new_grid_binary(
+    data: Data,
+    layer: Option<String>,
+    sharedgrid: State<SharedGrid>,
+    events: State<SharedEvents>,
+    limits: State<SharedLimits>,
+    regions: State<SharedRegions>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: DrawAccess,
+) -> Result<JsonValue, Status> {
+    let bytes = match lib::wire::read_body(data.open()) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            return Ok(rejected(&metrics, error));
+        }
+    };
+    let raw = match lib::wire::parse_binary(&bytes) {
+        Ok(raw) => raw,
+        Err(error) => {
+            return Ok(rejected(&metrics, error));
+        }
+    };
+
+    check_cells(&limits, &access.0, raw.width * raw.height)?;
+
+    let area = lib::regions::area(&regions, &access.0);
+    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+    let written = lib::layers::layer_mut(&mut sharedgrid_data, &layer).and_then(|grid_data| {
+        lib::wire::write_rgb24(grid_data, &raw, &lib::blend::BlendMode::Normal, &area)
+    });
+    match written {
+        Ok(()) => {
+            //the pixels are left out of the event, only where they went is told
+            let area = json!({
+                "zero_row": raw.zero_row,
+                "zero_column": raw.zero_column,
+                "width": raw.width,
+                "height": raw.height,
+                "layer": layer,
+            });
+            lib::events::publish(&events, "grid", "http", &area);
+            let (rows, columns) = (raw.height as i32, raw.width as i32);
+            let cells = lib::audit::rectangle(raw.zero_row, raw.zero_column, rows, columns);
+            lib::audit::record(&audit, &access.0, "grid", vec![cells]);
+            Ok(json!("success"))
+        }
+
+        Err(error) => {
+            Ok(rejected(&metrics, error))
         }
     }
+}
+
 
-    json!("")
 
+
+//answers with 429 if the client drew too many cells
+fn check_cells(limits: &SharedLimits, client: &Client, cells: usize) -> Result<(), Status> {
+    if lib::limits::take_cells(limits, &client.name, cells) {
+        Ok(())
+    } else {
+        Err(Status::TooManyRequests)
+    }
 }
 
 
+//every cell of the layer, for the audit log
+fn whole_grid(grid_data: &Grid) -> Vec<Rectangle> {
+    let (rows, columns) = (grid_data.grid.len() as i32, grid_data.grid[0].len() as i32);
+    vec![lib::audit::rectangle(0, 0, rows, columns)]
+}
 
 
 //get cell information via http, push rgb values in grid
 #[post("/", data = "<cell>")]
-pub fn add_cell(cell: Json<Cell>, sharedgrid: State<SharedGrid>) -> JsonValue {
-    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
-    let max_rows = &sharedgrid_data.grid.len();
-    let max_columns = &sharedgrid_data.grid[0].len();
-
-    let new_cell = Cell {
-        row: cell.row,
-        column: cell.column,
-        red: cell.red,
-        green: cell.green,
-        blue: cell.blue,
-    };
+pub fn add_cell(
+    cell: Json<Cell>,
+    sharedgrid: State<SharedGrid>,
+    shared_palettes: State<SharedPalettes>,
+    events: State<SharedEvents>,
+    limits: State<SharedLimits>,
+    regions: State<SharedRegions>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: DrawAccess,
+) -> Result<JsonValue, Status> {
+    check_cells(&limits, &access.0, 1)?;
+
+    let palette = lib::palette::active_colors(&shared_palettes);
+    let area = lib::regions::area(&regions, &access.0);
 
-    //checks values
-    let values = lib::err::is_cell_value_in_range(&new_cell, max_rows, max_columns);
-    match values {
+    match lib::draw::cell(&cell, &palette, &area, &sharedgrid) {
         Ok(()) => {
-            let color_arr = RGB {
-                red: new_cell.red,
-                green: new_cell.green,
-                blue: new_cell.blue,
-            };
-            //del -1
-            sharedgrid_data.grid[(new_cell.row) as usize][(new_cell.column) as usize] = color_arr;
-            json!("success")
+            lib::events::publish(&events, "cell", "http", &*cell);
+            let cells = lib::audit::rectangle(cell.row, cell.column, 1, 1);
+            lib::audit::record(&audit, &access.0, "cell", vec![cells]);
+            Ok(json!("success"))
         }
 
         Err(error) => {
-            let response = error.to_string();
-            // println!("{}", &response);
-            json!(response)
+            // println!("{}", &error);
+            Ok(rejected(&metrics, error))
         }
     }
 }
@@ -101,112 +182,917 @@ pub fn add_cell(cell: Json<Cell>, sharedgrid: State<SharedGrid>) -> JsonValue {
 
 //get line information via http, push rgb values in grid
 #[post("/", data = "<line>")]
-pub fn add_line(line: Json<Line>, sharedgrid: State<SharedGrid>) -> JsonValue {
-    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
-    let max_rows = &sharedgrid_data.grid.len();
-    let max_columns = &sharedgrid_data.grid[0].len();
+pub fn add_line(
+    line: Json<Line>,
+    sharedgrid: State<SharedGrid>,
+    shared_palettes: State<SharedPalettes>,
+    events: State<SharedEvents>,
+    limits: State<SharedLimits>,
+    regions: State<SharedRegions>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: DrawAccess,
+) -> Result<JsonValue, Status> {
+    check_cells(&limits, &access.0, line.length.max(0) as usize)?;
+
+    let palette = lib::palette::active_colors(&shared_palettes);
+    let area = lib::regions::area(&regions, &access.0);
+
+    match lib::draw::line(&line, &palette, &area, &sharedgrid) {
+        Ok(()) => {
+            lib::events::publish(&events, "line", "http", &*line);
+            let cells = if line.direction == 1 {
+                lib::audit::rectangle(line.row, line.column, line.length, 1)
+            } else {
+                lib::audit::rectangle(line.row, line.column, 1, line.length)
+            };
+            lib::audit::record(&audit, &access.0, "line", vec![cells]);
+            Ok(json!("success"))
+        }
+
+        Err(error) => {
+            // println!("{}", &error);
+            Ok(rejected(&metrics, error))
+        }
+    }
+}
+
+
+//clear the grid to the background color, or fill it with a color or a named pattern
+#[post("/", data = "<clear>")]
+pub fn clear(
+    clear: Json<Clear>,
+    sharedgrid: State<SharedGrid>,
+    shared_palettes: State<SharedPalettes>,
+    events: State<SharedEvents>,
+    limits: State<SharedLimits>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: AdminAccess,
+) -> Result<JsonValue, Status> {
+    check_cells(&limits, &access.0, lib::grid_cells(&sharedgrid))?;
 
-    //checks values
-    let values = lib::err::is_line_value_in_range(&line, max_rows, max_columns);
-    match values {
+    let palette = lib::palette::active_colors(&shared_palettes);
+    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+    let grid_data = match lib::layers::layer_mut(&mut sharedgrid_data, &clear.layer) {
+        Ok(grid_data) => grid_data,
+        Err(error) => {
+            return Ok(rejected(&metrics, error));
+        }
+    };
+    match lib::draw::clear(&clear, &palette, grid_data) {
         Ok(()) => {
+            lib::events::publish(&events, "clear", "http", &*clear);
+            lib::audit::record(&audit, &access.0, "clear", whole_grid(grid_data));
+            Ok(json!("success"))
+        }
+
+        Err(error) => {
+            Ok(rejected(&metrics, error))
+        }
+    }
+}
+
+
+//fill the grid with one of the built-in patterns
+#[post("/", data = "<pattern>")]
+pub fn pattern(
+    pattern: Json<ApiPattern>,
+    sharedgrid: State<SharedGrid>,
+    shared_palettes: State<SharedPalettes>,
+    events: State<SharedEvents>,
+    limits: State<SharedLimits>,
+    regions: State<SharedRegions>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: DrawAccess,
+) -> Result<JsonValue, Status> {
+    check_cells(&limits, &access.0, lib::grid_cells(&sharedgrid))?;
+
+    let palette = lib::palette::active_colors(&shared_palettes);
+    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+    let grid_data = match lib::layers::layer_mut(&mut sharedgrid_data, &pattern.layer) {
+        Ok(grid_data) => grid_data,
+        Err(error) => {
+            return Ok(rejected(&metrics, error));
+        }
+    };
 
-            if line.direction == 1 {
-                //println!("test1");
-                for j in line.row..(line.row + line.length) {
-
-                    let line_cell = Cell {
-                        row: j,
-                        column: line.column,
-                        red: line.red,
-                        green: line.green,
-                        blue: line.blue,
-                    };
-
-                    //checks values
-                    let values = lib::err::is_cell_value_in_range(&line_cell, max_rows, max_columns);
-                    match values {
-                        Ok(()) => {
-
-                            let color_arr = RGB {
-                                red: line.red,
-                                green: line.green,
-                                blue: line.blue,
-                            };
-
-                            sharedgrid_data.grid[j as usize][line.column as usize] = color_arr;
-
-                        }
-                        Err(error) => {
-                            let response = error.to_string();
-                            // println!("{}", &response);
-                            json!(response);
-                        }
-                    }
+    match lib::draw::pattern_params(&pattern, &palette, grid_data) {
+        Ok((new_pattern, params)) => {
+            let area = lib::regions::area(&regions, &access.0);
+            //a solid fill of the whole grid is a clear, which needs the admin scope. Clients
+            //that own regions only fill their own cells.
+            if let lib::pattern::Pattern::Solid = new_pattern {
+                if access.0.scope != Scope::Admin && !lib::regions::owns_regions(&area) {
+                    return Err(Status::Forbidden);
                 }
+            }
+            let filled = lib::regions::draw_masked(grid_data, &area, |grid_data| {
+                lib::pattern::fill_grid(grid_data, &new_pattern, &params)
+            });
+            if let Err(error) = filled {
+                return Ok(rejected(&metrics, error));
+            }
+            lib::events::publish(&events, "pattern", "http", &*pattern);
+            lib::audit::record(&audit, &access.0, "pattern", whole_grid(grid_data));
+            Ok(json!("success"))
+        }
 
-            } else {
-                //println!("test2");
-                for j in line.column..(line.column + line.length) {
-
-                    let line_cell = Cell {
-                        row: line.row,
-                        column: j,
-                        red: line.red,
-                        green: line.green,
-                        blue: line.blue,
-                    };
-
-                    //checks values
-                    let values = lib::err::is_cell_value_in_range(&line_cell, max_rows, max_columns);
-                    match values {
-                        Ok(()) => {
-
-                            let color_arr = RGB {
-                                red: line.red,
-                                green: line.green,
-                                blue: line.blue,
-                            };
-
-                            sharedgrid_data.grid[line.row as usize][j as usize] = color_arr;
-                        }
-                        Err(error) => {
-                            let response = error.to_string();
-                            // println!("{}", &response);
-                            json!(response);
-                        }
-                    }
+        Err(error) => {
+            Ok(rejected(&metrics, error))
+        }
+    }
+}
+
+
+//start the cellular automaton, optionally with a new rule ("B3/S23", "W30") and interval in ms
+#[post("/start", data = "<settings>")]
+pub fn automaton_start(
+    settings: Json<ApiAutomaton>,
+    shared_automaton: State<SharedAutomaton>,
+    regions: State<SharedRegions>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: DrawAccess,
+) -> JsonValue {
+    //the automaton runs on its own over the whole layer and would leave the region
+    if lib::regions::is_limited(&lib::regions::area(&regions, &access.0)) {
+        return rejected(
+            &metrics,
+            lib::err::rejection("region_limited", "The automaton is not allowed with regions"),
+        );
+    }
+    let mut automaton = shared_automaton.automaton.lock().expect("automaton lock failed");
+
+    if let Some(rule) = &settings.rule {
+        match lib::automata::parse_rule(rule) {
+            Ok(rule) => automaton.rule = rule,
+            Err(error) => {
+                return rejected(&metrics, error);
+            }
+        }
+    }
+    if let Some(interval) = settings.interval {
+        automaton.interval = time::Duration::from_millis(interval);
+    }
+    if settings.layer.is_some() {
+        automaton.layer = settings.layer.clone();
+    }
+
+    automaton.running = true;
+    lib::audit::record(&audit, &access.0, "automaton_start", Vec::new());
+    json!("running")
+}
+
+
+#[post("/stop")]
+pub fn automaton_stop(
+    shared_automaton: State<SharedAutomaton>,
+    audit: State<SharedAudit>,
+    access: DrawAccess,
+) -> JsonValue {
+    let mut automaton = shared_automaton.automaton.lock().expect("automaton lock failed");
+    automaton.running = false;
+    lib::audit::record(&audit, &access.0, "automaton_stop", Vec::new());
+    json!("stopped")
+}
+
+
+//advance a single generation, also while the automaton is stopped
+#[post("/step")]
+pub fn automaton_step(
+    shared_automaton: State<SharedAutomaton>,
+    sharedgrid: State<SharedGrid>,
+    regions: State<SharedRegions>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: DrawAccess,
+) -> JsonValue {
+    let area = lib::regions::area(&regions, &access.0);
+    let automaton = shared_automaton.automaton.lock().expect("automaton lock failed");
+    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+    match lib::layers::layer_mut(&mut sharedgrid_data, &automaton.layer) {
+        Ok(grid_data) => {
+            let stepped = lib::regions::draw_masked(grid_data, &area, |grid_data| {
+                lib::automata::step(grid_data, &automaton.rule)
+            });
+            if let Err(error) = stepped {
+                return rejected(&metrics, error);
+            }
+            lib::audit::record(&audit, &access.0, "automaton_step", whole_grid(grid_data));
+            json!("success")
+        }
+
+        Err(error) => {
+            rejected(&metrics, error)
+        }
+    }
+}
+
+
+#[post("/speed", data = "<settings>")]
+pub fn automaton_speed(
+    settings: Json<ApiAutomaton>,
+    shared_automaton: State<SharedAutomaton>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: DrawAccess,
+) -> JsonValue {
+    match settings.interval {
+        Some(interval) => {
+            let mut automaton = shared_automaton.automaton.lock().expect("automaton lock failed");
+            automaton.interval = time::Duration::from_millis(interval);
+            lib::audit::record(&audit, &access.0, "automaton_speed", Vec::new());
+            json!("success")
+        }
+        None => rejected(&metrics, lib::err::rejection("invalid_request", "Interval is missing")),
+    }
+}
+
+
+//draw text in the built-in font, or let it scroll through the grid
+#[post("/", data = "<text>")]
+pub fn text(
+    text: Json<ApiText>,
+    sharedgrid: State<SharedGrid>,
+    shared_marquee: State<SharedMarquee>,
+    shared_palettes: State<SharedPalettes>,
+    events: State<SharedEvents>,
+    limits: State<SharedLimits>,
+    regions: State<SharedRegions>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: DrawAccess,
+) -> Result<JsonValue, Status> {
+    let cells = lib::text::text_width(&text.text).saturating_mul(lib::font::GLYPH_HEIGHT);
+    check_cells(&limits, &access.0, cells.max(0) as usize)?;
+
+    //the text starts on the grid, it may only run over the right and the bottom edge.
+    //Scrolling text ignores the column.
+    let max_rows = lib::grid_rows(&sharedgrid) as usize;
+    let max_columns = lib::grid_columns(&sharedgrid) as usize;
+    let column = if text.scroll == Some(true) { 0 } else { text.column.unwrap_or(0) };
+    if let Err(error) = lib::err::is_position_in_range(text.row, column, &max_rows, &max_columns) {
+        return Ok(rejected(&metrics, error));
+    }
+
+    let palette = lib::palette::active_colors(&shared_palettes);
+    let color = match lib::color::resolve(&text.color, &palette) {
+        Ok((color, _alpha)) => color,
+        Err(error) => {
+            return Ok(rejected(&metrics, error));
+        }
+    };
+
+    let area = lib::regions::area(&regions, &access.0);
+
+    if text.scroll == Some(true) {
+        //the marquee runs on its own and would leave the region
+        if lib::regions::is_limited(&area) {
+            let message = "Scrolling text is not allowed with regions";
+            let error = lib::err::rejection("region_limited", message);
+            return Ok(rejected(&metrics, error));
+        }
+        let mut marquee = shared_marquee.marquee.lock().expect("marquee lock failed");
+        lib::text::start_marquee(&mut marquee, &text, color);
+        lib::events::publish(&events, "text", "http", &*text);
+        let columns = lib::grid_columns(&sharedgrid);
+        let cells = lib::audit::rectangle(text.row, 0, lib::font::GLYPH_HEIGHT, columns);
+        lib::audit::record(&audit, &access.0, "text", vec![cells]);
+        Ok(json!("scrolling"))
+    } else {
+        let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+        match lib::layers::layer_mut(&mut sharedgrid_data, &text.layer) {
+            Ok(grid_data) => {
+                let drawn = lib::regions::draw_masked(grid_data, &area, |grid_data| {
+                    lib::text::draw_text(grid_data, &text.text, text.row, column, color)
+                });
+                if let Err(error) = drawn {
+                    return Ok(rejected(&metrics, error));
                 }
+                lib::events::publish(&events, "text", "http", &*text);
+                let (rows, columns) = (lib::font::GLYPH_HEIGHT, lib::text::text_width(&text.text));
+                let cells = lib::audit::rectangle(text.row, column, rows, columns);
+                lib::audit::record(&audit, &access.0, "text", vec![cells]);
+                Ok(json!("success"))
+            }
+
+            Err(error) => {
+                Ok(rejected(&metrics, error))
             }
+        }
+    }
+}
+
+
+//stops the scrolling text, it stays where it is
+#[post("/stop")]
+pub fn text_stop(
+    shared_marquee: State<SharedMarquee>,
+    audit: State<SharedAudit>,
+    access: DrawAccess,
+) -> JsonValue {
+    let mut marquee = shared_marquee.marquee.lock().expect("marquee lock failed");
+    marquee.active = false;
+    lib::audit::record(&audit, &access.0, "text_stop", Vec::new());
+    json!("stopped")
+}
+
+
+#[get("/")]
+pub fn list_layers(
+    sharedgrid: State<SharedGrid>,
+    _access: ReadAccess,
+) -> JsonValue {
+    let sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+    let layers: Vec<JsonValue> = sharedgrid_data
+        .layers
+        .iter()
+        .map(|layer| {
+            json!({
+                "name": layer.name,
+                "z_index": layer.z_index,
+                "visible": layer.visible,
+                "opacity": layer.opacity,
+            })
+        })
+        .collect();
+
+    json!(layers)
+}
+
+
+//creates a layer or changes z-order, visibility and opacity of an existing one
+#[post("/", data = "<layer>")]
+pub fn set_layer(
+    layer: Json<ApiLayer>, sharedgrid: State<SharedGrid>,
+    regions: State<SharedRegions>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: DrawAccess,
+) -> JsonValue {
+    //layers cover the regions of everyone
+    if lib::regions::is_limited(&lib::regions::area(&regions, &access.0)) {
+        return rejected(
+            &metrics,
+            lib::err::rejection("region_limited", "Layers can not be changed with regions"),
+        );
+    }
+    if let Some(opacity) = layer.opacity {
+        if !(opacity >= 0.0 && opacity <= 1.0) {
+            return rejected(
+                &metrics,
+                lib::err::rejection("invalid_opacity", "Opacity must be between 0 and 1"),
+            );
+        }
+    }
+
+    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+
+    let exists = sharedgrid_data.layers.iter().any(|existing| existing.name == layer.name);
+    if !exists {
+        let z_index = match sharedgrid_data.layers.last() {
+            Some(top) => top.z_index + 1,
+            None => 0,
+        };
+        let content =
+            lib::layers::layer_mut(&mut sharedgrid_data, &None).expect("content layer exists");
+        let max_rows = content.grid.len() as i32;
+        let max_columns = content.grid[0].len() as i32;
+        let background = content.background;
+        let new_layer =
+            lib::layers::new_layer(&layer.name, z_index, max_columns, max_rows, background);
+        sharedgrid_data.layers.push(new_layer);
+    }
+
+    let existing = sharedgrid_data.layers.iter_mut().find(|existing| existing.name == layer.name);
+    if let Some(existing) = existing {
+        if let Some(z_index) = layer.z_index {
+            existing.z_index = z_index;
+        }
+        if let Some(visible) = layer.visible {
+            existing.visible = visible;
+        }
+        if let Some(opacity) = layer.opacity {
+            existing.opacity = opacity;
+        }
+    }
+
+    lib::layers::sort_layers(&mut sharedgrid_data);
+    lib::audit::record(&audit, &access.0, "layer", Vec::new());
+    json!("success")
+}
+
+
+#[delete("/<name>")]
+pub fn delete_layer(
+    name: String, sharedgrid: State<SharedGrid>,
+    regions: State<SharedRegions>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: DrawAccess,
+) -> JsonValue {
+    //layers cover the regions of everyone
+    if lib::regions::is_limited(&lib::regions::area(&regions, &access.0)) {
+        return rejected(
+            &metrics,
+            lib::err::rejection("region_limited", "Layers can not be changed with regions"),
+        );
+    }
+    if name == lib::layers::DEFAULT_LAYER {
+        return rejected(
+            &metrics,
+            lib::err::rejection("invalid_request", "The content layer can not be removed"),
+        );
+    }
+
+    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+    let layer_count = sharedgrid_data.layers.len();
+    sharedgrid_data.layers.retain(|layer| layer.name != name);
+
+    if sharedgrid_data.layers.len() < layer_count {
+        lib::audit::record(&audit, &access.0, "delete_layer", Vec::new());
+        json!("success")
+    } else {
+        rejected(&metrics, lib::err::rejection("unknown_layer", "Unknown layer"))
+    }
+}
+
+
+#[get("/")]
+pub fn list_palettes(
+    shared_palettes: State<SharedPalettes>,
+    _access: ReadAccess,
+) -> JsonValue {
+    let palettes = shared_palettes.palettes.lock().expect("palette lock failed");
+    let names: Vec<&String> = palettes.palettes.iter().map(|palette| &palette.name).collect();
+
+    json!({
+        "palettes": names,
+        "active": palettes.palettes[palettes.active].name,
+    })
+}
+
+
+//uploads a palette, an existing palette with the same name is replaced
+#[post("/", data = "<palette>")]
+pub fn upload_palette(
+    palette: Json<ApiPalette>,
+    sharedgrid: State<SharedGrid>,
+    shared_palettes: State<SharedPalettes>,
+    regions: State<SharedRegions>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: DrawAccess,
+) -> JsonValue {
+    //the palette recolors the cells of everyone
+    if lib::regions::is_limited(&lib::regions::area(&regions, &access.0)) {
+        return rejected(
+            &metrics,
+            lib::err::rejection("region_limited", "Palettes can not be changed with regions"),
+        );
+    }
+    let active = lib::palette::active_colors(&shared_palettes);
+    let colors: std::io::Result<Vec<RGB>> = palette
+        .colors
+        .iter()
+        .map(|color| lib::color::resolve(color, &active).map(|(color, _alpha)| color))
+        .collect();
+    let colors = match colors {
+        Ok(colors) => colors,
+        Err(error) => {
+            return rejected(&metrics, error);
+        }
+    };
+
+    let new_palette = Palette {
+        name: palette.name.clone(),
+        colors,
+    };
+    match add_palette(new_palette, &sharedgrid, &shared_palettes) {
+        Ok(()) => {
+            lib::audit::record(&audit, &access.0, "palette", Vec::new());
             json!("success")
         }
 
         Err(error) => {
-            let response = error.to_string();
-            // println!("{}", &response);
-            json!(response)
+            rejected(&metrics, error)
+        }
+    }
+}
+
+
+//loads a .gpl or .hex palette file from the server
+#[post("/file", data = "<file>")]
+pub fn load_palette(
+    file: Json<ApiPaletteFile>,
+    sharedgrid: State<SharedGrid>,
+    shared_palettes: State<SharedPalettes>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: AdminAccess,
+) -> JsonValue {
+    let added = lib::palette::load_palette(std::path::Path::new(&file.path))
+        .and_then(|palette| add_palette(palette, &sharedgrid, &shared_palettes));
+    match added {
+        Ok(()) => {
+            lib::audit::record(&audit, &access.0, "palette_file", Vec::new());
+            json!("success")
+        }
+        Err(error) => {
+            rejected(&metrics, error)
+        }
+    }
+}
+
+
+//answers with the error as text, like every handler does, and counts why it was rejected
+fn rejected(metrics: &SharedMetrics, error: std::io::Error) -> JsonValue {
+    lib::metrics::rejected(metrics, lib::err::reason(&error));
+    let response = error.to_string();
+    json!(response)
+}
+
+
+//uploading the active palette recolors the grid right away
+fn add_palette(
+    palette: Palette,
+    sharedgrid: &SharedGrid,
+    shared_palettes: &SharedPalettes,
+) -> std::io::Result<()> {
+    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+    let mut palettes = shared_palettes.palettes.lock().expect("palette lock failed");
+    let old = palettes.palettes[palettes.active].colors.clone();
+
+    lib::palette::add_palette(&mut palettes, palette)?;
+    let new = &palettes.palettes[palettes.active].colors;
+    lib::palette::recolor(&mut sharedgrid_data, &old, new);
+    Ok(())
+}
+
+
+//swaps the active palette, every cell drawn with a palette index changes its color
+#[post("/activate/<name>")]
+pub fn activate_palette(
+    name: String,
+    sharedgrid: State<SharedGrid>,
+    shared_palettes: State<SharedPalettes>,
+    regions: State<SharedRegions>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: DrawAccess,
+) -> JsonValue {
+    //the palette recolors the cells of everyone
+    if lib::regions::is_limited(&lib::regions::area(&regions, &access.0)) {
+        return rejected(
+            &metrics,
+            lib::err::rejection("region_limited", "Palettes can not be changed with regions"),
+        );
+    }
+    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+    let mut palettes = shared_palettes.palettes.lock().expect("palette lock failed");
+
+    match palettes.palettes.iter().position(|palette| palette.name == name) {
+        Some(position) => {
+            let old = palettes.palettes[palettes.active].colors.clone();
+            palettes.active = position;
+            lib::palette::recolor(&mut sharedgrid_data, &old, &palettes.palettes[position].colors);
+            lib::audit::record(&audit, &access.0, "activate_palette", Vec::new());
+            json!("success")
         }
+        None => rejected(&metrics, lib::err::rejection("unknown_palette", "Unknown palette")),
+    }
+}
+
+
+//rotates the colors of the active palette by shift places, for color cycling animations
+#[post("/cycle", data = "<cycle>")]
+pub fn cycle_palette(
+    cycle: Json<ApiPaletteCycle>,
+    sharedgrid: State<SharedGrid>,
+    shared_palettes: State<SharedPalettes>,
+    regions: State<SharedRegions>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: DrawAccess,
+) -> JsonValue {
+    //the palette recolors the cells of everyone
+    if lib::regions::is_limited(&lib::regions::area(&regions, &access.0)) {
+        return rejected(
+            &metrics,
+            lib::err::rejection("region_limited", "Palettes can not be changed with regions"),
+        );
     }
+    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+    let mut palettes = shared_palettes.palettes.lock().expect("palette lock failed");
+    let active = palettes.active;
+    let colors = &mut palettes.palettes[active].colors;
+    let old = colors.clone();
+
+    let shift = cycle.shift.rem_euclid(colors.len() as i32) as usize;
+    colors.rotate_right(shift);
+    lib::palette::recolor(&mut sharedgrid_data, &old, colors);
+    lib::audit::record(&audit, &access.0, "cycle_palette", Vec::new());
+    json!("success")
+}
+
+
+#[get("/")]
+pub fn list_regions(
+    regions: State<SharedRegions>,
+    _access: ReadAccess,
+) -> JsonValue {
+    let regions = regions.regions.lock().expect("regions lock failed");
+    json!(regions.regions)
 }
 
 
-#[get("/intervention/<intervention>")]
+//gives a rectangle of the grid to the token with the name of the owner
+#[post("/", data = "<region>")]
+pub fn add_region(
+    region: Json<Region>,
+    sharedgrid: State<SharedGrid>,
+    regions: State<SharedRegions>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: AdminAccess,
+) -> JsonValue {
+    if region.rows <= 0 || region.columns <= 0 {
+        return rejected(
+            &metrics,
+            lib::err::rejection("invalid_request", "Regions need at least one row and one column"),
+        );
+    }
+
+    let (max_rows, max_columns) = {
+        let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+        let content =
+            lib::layers::layer_mut(&mut sharedgrid_data, &None).expect("content layer exists");
+        (content.grid.len(), content.grid[0].len())
+    };
+    if !lib::regions::on_grid(&region, max_rows, max_columns) {
+        return rejected(
+            &metrics,
+            lib::err::rejection("invalid_request", "Region is outside of the grid"),
+        );
+    }
+
+    let cells = lib::audit::rectangle(region.row, region.column, region.rows, region.columns);
+    lib::audit::record(&audit, &access.0, "region", vec![cells]);
+
+    let mut regions = regions.regions.lock().expect("regions lock failed");
+    regions.regions.push(region.into_inner());
+    json!("success")
+}
+
+
+//removes every region of the owner
+#[delete("/<owner>")]
+pub fn delete_regions(
+    owner: String,
+    regions: State<SharedRegions>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: AdminAccess,
+) -> JsonValue {
+    let mut regions = regions.regions.lock().expect("regions lock failed");
+    let region_count = regions.regions.len();
+    regions.regions.retain(|region| region.owner != owner);
+
+    if regions.regions.len() < region_count {
+        lib::audit::record(&audit, &access.0, "delete_regions", Vec::new());
+        json!("success")
+    } else {
+        rejected(&metrics, lib::err::rejection("invalid_request", "Unknown owner"))
+    }
+}
+
+
+//pauses the program and covers the grid with an overlay, the grid itself is kept
+#[post("/", data = "<settings>")]
 pub fn intervention(
-    intervention: bool,
+    settings: Json<ApiIntervention>,
     sharedgrid: State<SharedGrid>,
+    shared_palettes: State<SharedPalettes>,
+    shared_intervention: State<SharedIntervention>,
     program_paused: State<Arc<AtomicBool>>,
+    audit: State<SharedAudit>,
+    metrics: State<SharedMetrics>,
+    access: AdminAccess,
 ) -> JsonValue {
-    lib::clear_grid(&sharedgrid);
+    let palette = lib::palette::active_colors(&shared_palettes);
 
-    if intervention {
-        lib::make_checker_board(&sharedgrid);
+    match lib::intervention::start(
+        &settings,
+        &palette,
+        &sharedgrid,
+        &shared_intervention,
+        &program_paused,
+    ) {
+        Ok(()) => {
+            let (rows, columns) = (lib::grid_rows(&sharedgrid), lib::grid_columns(&sharedgrid));
+            let cells = lib::audit::rectangle(0, 0, rows, columns);
+            lib::audit::record(&audit, &access.0, "intervention", vec![cells]);
+            json!("paused")
+        }
 
-        thread::sleep(time::Duration::from_millis(100));
-        program_paused.store(intervention, Ordering::Relaxed);
-        json!("paused")
-    } else {
-        thread::sleep(time::Duration::from_millis(50));
-        program_paused.store(intervention, Ordering::Relaxed);
-        json!("unpaused")
+        Err(error) => {
+            rejected(&metrics, error)
+        }
     }
 }
+
+
+//removes the overlay, the grid is shown as it was
+#[post("/resume")]
+pub fn intervention_resume(
+    sharedgrid: State<SharedGrid>,
+    shared_intervention: State<SharedIntervention>,
+    program_paused: State<Arc<AtomicBool>>,
+    audit: State<SharedAudit>,
+    access: AdminAccess,
+) -> JsonValue {
+    lib::intervention::resume(&sharedgrid, &shared_intervention, &program_paused);
+    lib::audit::record(&audit, &access.0, "intervention_resume", Vec::new());
+    json!("unpaused")
+}
+
+
+#[get("/")]
+pub fn intervention_status(
+    shared_intervention: State<SharedIntervention>,
+    _access: ReadAccess,
+) -> JsonValue {
+    JsonValue(lib::intervention::status(&shared_intervention))
+}
+
+
+//who changed the cell when, the latest change first
+#[get("/<row>/<column>/history")]
+pub fn cell_history(
+    row: i32,
+    column: i32,
+    audit: State<SharedAudit>,
+    _access: ReadAccess,
+) -> JsonValue {
+    json!(lib::audit::history(&audit, row, column))
+}
+
+
+#[get("/")]
+pub fn schedule_status(
+    shared_schedule: State<SharedSchedule>,
+    _access: ReadAccess,
+) -> JsonValue {
+    JsonValue(lib::schedule::status(&shared_schedule))
+}
+
+
+//the playlist goes on, timed scenes are shown again
+#[post("/start")]
+pub fn schedule_start(
+    shared_schedule: State<SharedSchedule>,
+    audit: State<SharedAudit>,
+    access: DrawAccess,
+) -> JsonValue {
+    let mut schedule = shared_schedule.schedule.lock().expect("schedule lock failed");
+    schedule.running = true;
+    lib::audit::record(&audit, &access.0, "schedule_start", Vec::new());
+    json!("running")
+}
+
+
+//the current scene stays on the display until the schedule is started again
+#[post("/stop")]
+pub fn schedule_stop(
+    shared_schedule: State<SharedSchedule>,
+    audit: State<SharedAudit>,
+    access: DrawAccess,
+) -> JsonValue {
+    let mut schedule = shared_schedule.schedule.lock().expect("schedule lock failed");
+    schedule.running = false;
+    lib::audit::record(&audit, &access.0, "schedule_stop", Vec::new());
+    json!("stopped")
+}
+
+
+#[post("/skip")]
+pub fn schedule_skip(
+    shared_schedule: State<SharedSchedule>,
+    audit: State<SharedAudit>,
+    access: DrawAccess,
+) -> JsonValue {
+    lib::schedule::skip(&shared_schedule);
+    lib::audit::record(&audit, &access.0, "schedule_skip", Vec::new());
+    json!("success")
+}
+
+
+//the snapshots as an animated gif, from and to are seconds since 1970
+#[get("/?<from>&<to>&<scale>&<delay>")]
+pub fn timelapse(
+    from: Option<u64>,
+    to: Option<u64>,
+    scale: Option<usize>,
+    delay: Option<u64>,
+    shared_timelapse: State<SharedTimelapse>,
+    metrics: State<SharedMetrics>,
+    _access: ReadAccess,
+) -> Result<Content<Vec<u8>>, JsonValue> {
+    let export = lib::timelapse::Export {
+        from,
+        to,
+        scale: scale.unwrap_or(8),
+        delay: delay.unwrap_or(100),
+    };
+
+    match lib::timelapse::export_gif(&shared_timelapse, &export) {
+        Ok(gif) => Ok(Content(ContentType::GIF, gif)),
+        Err(error) => {
+            Err(rejected(&metrics, error))
+        }
+    }
+}
+
+
+//what the display is doing, for monitoring. A growing seconds_since_last_frame means the
+//video loop has stalled.
+#[get("/")]
+pub fn status(
+    sharedgrid: State<SharedGrid>,
+    program_paused: State<Arc<AtomicBool>>,
+    shared_intervention: State<SharedIntervention>,
+    shared_metrics: State<SharedMetrics>,
+    _access: ReadAccess,
+) -> JsonValue {
+    let (rows, columns) = (lib::grid_rows(&sharedgrid), lib::grid_columns(&sharedgrid));
+    let intervention = lib::intervention::status(&shared_intervention);
+    let metrics = shared_metrics.metrics.lock().expect("metrics lock failed");
+    let since_last_frame = metrics
+        .last_frame
+        .map(|last_frame| lib::metrics::seconds(last_frame.elapsed()));
+
+    json!({
+        "rows": rows,
+        "columns": columns,
+        "paused": program_paused.load(Ordering::Relaxed),
+        "intervention": intervention["active"],
+        "uptime": metrics.started.elapsed().as_secs(),
+        "frames": metrics.frames,
+        "fps": metrics.fps,
+        "cells_per_second": metrics.cells_per_second,
+        "seconds_since_last_frame": since_last_frame,
+        "lock_wait": {
+            "total": lib::metrics::seconds(metrics.lock_wait),
+            "max": lib::metrics::seconds(metrics.lock_wait_max),
+        },
+        "requests": metrics.requests,
+        "rejected": metrics.rejected,
+    })
+}
+
+
+//the metrics for Prometheus, in its text format
+#[get("/")]
+pub fn prometheus_metrics(
+    program_paused: State<Arc<AtomicBool>>,
+    shared_metrics: State<SharedMetrics>,
+    _access: ReadAccess,
+) -> Content<String> {
+    let paused = program_paused.load(Ordering::Relaxed);
+    let text = lib::metrics::prometheus(&shared_metrics, paused);
+    Content(ContentType::Plain, text)
+}
+
+
+#[catch(401)]
+pub fn unauthorized() -> JsonValue {
+    json!("Missing or unknown token")
+}
+
+
+#[catch(403)]
+pub fn forbidden() -> JsonValue {
+    json!("The token does not allow this request")
+}
+
+
+#[catch(429)]
+pub fn too_many_requests() -> JsonValue {
+    json!("Too many requests, try again later")
+}
+
+
+#[catch(503)]
+pub fn shutting_down() -> JsonValue {
+    json!("The program is shutting down")
+}
+
+//stops the program like Escape does, the program waits until this answer was sent
+#[post("/")]
+pub fn shutdown(shared_shutdown: State<SharedShutdown>, access: AdminAccess) -> JsonValue {
+    let reason = format!("requested by {}", access.0.name);
+    lib::shutdown::request(&shared_shutdown, &reason, 0);
+    json!("success")
+}
@@ -0,0 +1,292 @@
+use std::fs;
+use std::io::{Error, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::lib::api::{ApiAutomaton, ApiPattern, ApiText, Clear};
+use crate::lib::automata::{self, SharedAutomaton};
+use crate::lib::data::SharedGrid;
+use crate::lib::palette::{self, SharedPalettes};
+use crate::lib::text::{self, SharedMarquee};
+use crate::lib::{color, draw, image, layers, pattern};
+
+//how long a scene is shown if it has no duration, in seconds
+const DEFAULT_DURATION: u64 = 60;
+
+//what the display shows for a while, every part is optional and they are applied in this order
+#[derive(Deserialize)]
+pub struct Scene {
+    pub name: Option<String>,
+    //seconds
+    pub duration: Option<u64>,
+    //"HH:MM" in local time, the scene interrupts the playlist every day at this time
+    pub at: Option<String>,
+    pub clear: Option<Clear>,
+    pub pattern: Option<ApiPattern>,
+    //a GIF or a PNG file, the first frame of a GIF is drawn with one pixel per cell
+    pub image: Option<PathBuf>,
+    pub text: Option<ApiText>,
+    pub automaton: Option<ApiAutomaton>,
+}
+
+struct Timed {
+    hour: i32,
+    minute: i32,
+    scene: Scene,
+    //day of the year it was last shown, so it is shown once a day
+    shown_on: Option<i32>,
+}
+
+pub struct Schedule {
+    playlist: Vec<Scene>,
+    timed: Vec<Timed>,
+    pub running: bool,
+    //position in the playlist
+    current: Option<usize>,
+    //the timed scene that interrupts the playlist
+    interrupted_by: Option<usize>,
+    //seconds until the next scene
+    remaining: u64,
+    skip: bool,
+}
+
+pub struct SharedSchedule {
+    pub schedule: Arc<Mutex<Schedule>>,
+}
+
+pub fn schedule_init(scenes: Vec<Scene>) -> std::io::Result<SharedSchedule> {
+    let mut playlist = Vec::new();
+    let mut timed = Vec::new();
+
+    for scene in scenes {
+        match &scene.at {
+            Some(at) => {
+                let (hour, minute) = parse_time(at)?;
+                timed.push(Timed {
+                    hour,
+                    minute,
+                    scene,
+                    shown_on: None,
+                });
+            }
+            None => playlist.push(scene),
+        }
+    }
+
+    Ok(SharedSchedule {
+        schedule: Arc::new(Mutex::new(Schedule {
+            playlist,
+            timed,
+            running: true,
+            current: None,
+            interrupted_by: None,
+            remaining: 0,
+            skip: false,
+        })),
+    })
+}
+
+//the schedule file is a json list of scenes
+pub fn load_schedule(path: &Path) -> std::io::Result<Vec<Scene>> {
+    let contents = fs::read_to_string(path)?;
+    match serde_json::from_str(&contents) {
+        Ok(scenes) => Ok(scenes),
+        Err(error) => Err(Error::new(ErrorKind::Other, error.to_string())),
+    }
+}
+
+fn parse_time(at: &str) -> std::io::Result<(i32, i32)> {
+    let parts: Vec<&str> = at.trim().split(':').collect();
+    let invalid = || Error::new(ErrorKind::Other, format!("Invalid time: {}", at));
+    if parts.len() != 2 {
+        return Err(invalid());
+    }
+
+    let hour = parts[0].parse::<i32>().map_err(|_| invalid())?;
+    let minute = parts[1].parse::<i32>().map_err(|_| invalid())?;
+    if hour < 0 || hour > 23 || minute < 0 || minute > 59 {
+        return Err(invalid());
+    }
+    Ok((hour, minute))
+}
+
+//the next scene is shown right away
+pub fn skip(shared_schedule: &SharedSchedule) {
+    let mut schedule = shared_schedule
+        .schedule
+        .lock()
+        .expect("schedule lock failed");
+    schedule.skip = true;
+}
+
+//what is shown and for how much longer
+pub fn status(shared_schedule: &SharedSchedule) -> serde_json::Value {
+    let schedule = shared_schedule
+        .schedule
+        .lock()
+        .expect("schedule lock failed");
+    let scene = match (schedule.interrupted_by, schedule.current) {
+        (Some(i), _) => Some(&schedule.timed[i].scene),
+        (None, Some(i)) => Some(&schedule.playlist[i]),
+        (None, None) => None,
+    };
+
+    json!({
+        "running": schedule.running,
+        "scene": scene.map(|scene| scene.name.clone()),
+        "position": schedule.current,
+        "timed": schedule.interrupted_by.is_some(),
+        "remaining": schedule.remaining,
+    })
+}
+
+//checks the schedule once a second. While the program is paused, the time of the scene stands
+//still and timed scenes are left out.
+pub fn run(
+    shared_schedule: SharedSchedule,
+    program_paused: Arc<AtomicBool>,
+    sharedgrid: SharedGrid,
+    shared_palettes: SharedPalettes,
+    shared_automaton: SharedAutomaton,
+    shared_marquee: SharedMarquee,
+) {
+    loop {
+        if !program_paused.load(Ordering::Relaxed) {
+            let mut schedule = shared_schedule
+                .schedule
+                .lock()
+                .expect("schedule lock failed");
+            if schedule.running {
+                if let Some(scene) = next_scene(&mut schedule) {
+                    let result = apply_scene(
+                        scene,
+                        &sharedgrid,
+                        &shared_palettes,
+                        &shared_automaton,
+                        &shared_marquee,
+                    );
+                    if let Err(error) = result {
+                        println!("Scene could not be shown: {}", error);
+                    }
+                }
+            }
+        }
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+//the scene that starts now, if any. Timed scenes come first.
+fn next_scene(schedule: &mut Schedule) -> Option<&Scene> {
+    let now = time::now();
+    let due = schedule.timed.iter().position(|timed| {
+        timed.hour == now.tm_hour
+            && timed.minute == now.tm_min
+            && timed.shown_on != Some(now.tm_yday)
+    });
+    if let Some(i) = due {
+        schedule.timed[i].shown_on = Some(now.tm_yday);
+        schedule.interrupted_by = Some(i);
+        schedule.remaining = schedule.timed[i].scene.duration.unwrap_or(DEFAULT_DURATION);
+        schedule.skip = false;
+        return Some(&schedule.timed[i].scene);
+    }
+
+    if schedule.remaining > 1 && !schedule.skip {
+        schedule.remaining -= 1;
+        return None;
+    }
+    schedule.skip = false;
+    schedule.interrupted_by = None;
+    if schedule.playlist.is_empty() {
+        schedule.remaining = 0;
+        return None;
+    }
+
+    //after a timed scene the playlist goes on with the next scene
+    let next = match schedule.current {
+        Some(current) => (current + 1) % schedule.playlist.len(),
+        None => 0,
+    };
+    schedule.current = Some(next);
+    schedule.remaining = schedule.playlist[next].duration.unwrap_or(DEFAULT_DURATION);
+    Some(&schedule.playlist[next])
+}
+
+//a scene replaces whatever was running before it
+pub fn apply_scene(
+    scene: &Scene,
+    sharedgrid: &SharedGrid,
+    shared_palettes: &SharedPalettes,
+    shared_automaton: &SharedAutomaton,
+    shared_marquee: &SharedMarquee,
+) -> std::io::Result<()> {
+    shared_automaton
+        .automaton
+        .lock()
+        .expect("automaton lock failed")
+        .running = false;
+    shared_marquee
+        .marquee
+        .lock()
+        .expect("marquee lock failed")
+        .active = false;
+
+    let colors = palette::active_colors(shared_palettes);
+
+    if let Some(clear) = &scene.clear {
+        let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+        let grid_data = layers::layer_mut(&mut sharedgrid_data, &clear.layer)?;
+        draw::clear(clear, &colors, grid_data)?;
+    }
+
+    if let Some(scene_pattern) = &scene.pattern {
+        let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+        let grid_data = layers::layer_mut(&mut sharedgrid_data, &scene_pattern.layer)?;
+        let (new_pattern, params) = draw::pattern_params(scene_pattern, &colors, grid_data)?;
+        pattern::fill_grid(grid_data, &new_pattern, &params);
+    }
+
+    if let Some(path) = &scene.image {
+        let (width, height, pixels) = image::load(path)?;
+
+        let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+        let grid_data = layers::layer_mut(&mut sharedgrid_data, &None)?;
+        draw::image(width, height, &pixels, grid_data)?;
+    }
+
+    if let Some(scene_text) = &scene.text {
+        let (color, _alpha) = color::resolve(&scene_text.color, &colors)?;
+        if scene_text.scroll == Some(true) {
+            let mut marquee = shared_marquee.marquee.lock().expect("marquee lock failed");
+            text::start_marquee(&mut marquee, scene_text, color);
+        } else {
+            let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+            let grid_data = layers::layer_mut(&mut sharedgrid_data, &scene_text.layer)?;
+            let column = scene_text.column.unwrap_or(0);
+            text::draw_text(grid_data, &scene_text.text, scene_text.row, column, color);
+        }
+    }
+
+    if let Some(settings) = &scene.automaton {
+        let mut automaton = shared_automaton
+            .automaton
+            .lock()
+            .expect("automaton lock failed");
+        if let Some(rule) = &settings.rule {
+            automaton.rule = automata::parse_rule(rule)?;
+        }
+        if let Some(interval) = settings.interval {
+            automaton.interval = Duration::from_millis(interval);
+        }
+        if settings.layer.is_some() {
+            automaton.layer = settings.layer.clone();
+        }
+        automaton.running = true;
+    }
+    Ok(())
+}
@@ -0,0 +1,181 @@
+use std::io::Read;
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::{Data, Request, Response};
+use signal_hook::iterator::Signals;
+
+use crate::lib::audit::{self, SharedAudit};
+use crate::lib::events::{self, SharedEvents};
+
+//how long the requests that were accepted before the shutdown may take to finish
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub struct Shutdown {
+    //why the program stops, none while it runs
+    pub reason: Option<String>,
+    //exit status of the program, 128 + the signal number if it was stopped by a signal
+    pub code: i32,
+    //requests that were received, but whose answer was not sent yet
+    in_flight: usize,
+}
+
+pub struct SharedShutdown {
+    pub shutdown: Arc<Mutex<Shutdown>>,
+}
+
+//counts the requests in flight, so the shutdown can wait until they are answered
+pub struct RequestTracker {
+    pub shutdown: SharedShutdown,
+}
+
+//the body of an answer, the request counts as finished once it was sent and is dropped
+struct Tracked<R> {
+    body: R,
+    shutdown: Arc<Mutex<Shutdown>>,
+}
+
+pub fn shutdown_init() -> SharedShutdown {
+    SharedShutdown {
+        shutdown: Arc::new(Mutex::new(Shutdown {
+            reason: None,
+            code: 0,
+            in_flight: 0,
+        })),
+    }
+}
+
+//the video loop stops at its next frame, the first reason is kept
+pub fn request(shared_shutdown: &SharedShutdown, reason: &str, code: i32) {
+    let mut shutdown = shared_shutdown
+        .shutdown
+        .lock()
+        .expect("shutdown lock failed");
+    if shutdown.reason.is_none() {
+        println!("shutting down: {}", reason);
+        shutdown.reason = Some(String::from(reason));
+        shutdown.code = code;
+    }
+}
+
+pub fn is_requested(shared_shutdown: &SharedShutdown) -> bool {
+    shared_shutdown
+        .shutdown
+        .lock()
+        .expect("shutdown lock failed")
+        .reason
+        .is_some()
+}
+
+//SIGINT and SIGTERM stop the program the same way as Escape
+pub fn listen_signals(shared_shutdown: SharedShutdown) -> std::io::Result<()> {
+    let signals = Signals::new(&[signal_hook::SIGINT, signal_hook::SIGTERM])?;
+    for signal in signals.forever() {
+        let name = if signal == signal_hook::SIGINT {
+            "SIGINT"
+        } else {
+            "SIGTERM"
+        };
+        request(&shared_shutdown, name, 128 + signal);
+    }
+    Ok(())
+}
+
+//waits for the requests in flight, writes out what is still buffered and returns the exit
+//code. New requests are refused with 503 by the access guards. Rocket 0.4 can't be stopped
+//from outside, the HTTP server ends with the process.
+pub fn finish(
+    shared_shutdown: &SharedShutdown,
+    shared_audit: &SharedAudit,
+    shared_events: &SharedEvents,
+    recorder: Option<JoinHandle<()>>,
+) -> i32 {
+    let started = Instant::now();
+    loop {
+        let in_flight = shared_shutdown
+            .shutdown
+            .lock()
+            .expect("shutdown lock failed")
+            .in_flight;
+        if in_flight == 0 {
+            break;
+        }
+        if started.elapsed() >= DRAIN_TIMEOUT {
+            println!("{} requests were not answered in time", in_flight);
+            break;
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    let mut failed = false;
+
+    //the recorder writes the events that are left and stops
+    events::close(shared_events);
+    if let Some(recorder) = recorder {
+        if recorder.join().is_err() {
+            println!("The recording could not be finished");
+            failed = true;
+        }
+    }
+
+    if let Err(error) = audit::flush(shared_audit) {
+        println!("Could not write the audit log: {}", error);
+        failed = true;
+    }
+
+    if failed {
+        1
+    } else {
+        shared_shutdown
+            .shutdown
+            .lock()
+            .expect("shutdown lock failed")
+            .code
+    }
+}
+
+fn finished(shutdown: &Mutex<Shutdown>) {
+    let mut shutdown = shutdown.lock().expect("shutdown lock failed");
+    shutdown.in_flight = shutdown.in_flight.saturating_sub(1);
+}
+
+impl<R: Read> Read for Tracked<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.body.read(buf)
+    }
+}
+
+impl<R> Drop for Tracked<R> {
+    fn drop(&mut self) {
+        finished(&self.shutdown);
+    }
+}
+
+impl Fairing for RequestTracker {
+    fn info(&self) -> Info {
+        Info {
+            name: "Request tracker",
+            kind: Kind::Request | Kind::Response,
+        }
+    }
+
+    fn on_request(&self, _request: &mut Request, _data: &Data) {
+        self.shutdown
+            .shutdown
+            .lock()
+            .expect("shutdown lock failed")
+            .in_flight += 1;
+    }
+
+    fn on_response(&self, _request: &Request, response: &mut Response) {
+        match response.take_body() {
+            Some(body) => {
+                let shutdown = self.shutdown.shutdown.clone();
+                response.set_raw_body(body.map(|body| Tracked { body, shutdown }));
+            }
+            None => finished(&self.shutdown.shutdown),
+        }
+    }
+}
@@ -0,0 +1,194 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::lib::api::ApiText;
+use crate::lib::data::{Grid, SharedGrid, RGB};
+use crate::lib::font::{glyph, GLYPH_HEIGHT, GLYPH_WIDTH};
+use crate::lib::layers;
+
+//one empty column between characters
+const CHARACTER_SPACING: i32 = 1;
+
+//text that scrolls from right to left through the rows row..row + GLYPH_HEIGHT
+pub struct Marquee {
+    pub text: String,
+    pub row: i32,
+    pub color: RGB,
+    pub active: bool,
+    pub offset: i32,
+    pub interval: Duration,
+    pub last_step: Instant,
+    pub layer: Option<String>,
+}
+
+pub struct SharedMarquee {
+    pub marquee: Arc<Mutex<Marquee>>,
+}
+
+pub fn marquee_init() -> SharedMarquee {
+    let marquee = Marquee {
+        text: String::new(),
+        row: 0,
+        color: RGB {
+            red: 255,
+            green: 255,
+            blue: 255,
+        },
+        active: false,
+        offset: 0,
+        interval: Duration::from_millis(100),
+        last_step: Instant::now(),
+        layer: None,
+    };
+
+    SharedMarquee {
+        marquee: Arc::new(Mutex::new(marquee)),
+    }
+}
+
+//the text enters at the right edge of the grid
+pub fn start_marquee(marquee: &mut Marquee, text: &ApiText, color: RGB) {
+    marquee.text = text.text.clone();
+    marquee.row = text.row;
+    marquee.color = color;
+    marquee.layer = text.layer.clone();
+    marquee.offset = 0;
+    if let Some(interval) = text.interval {
+        marquee.interval = Duration::from_millis(interval);
+    }
+    marquee.active = true;
+}
+
+pub fn text_width(text: &str) -> i32 {
+    let characters = text.chars().count() as i32;
+    if characters == 0 {
+        0
+    } else {
+        characters.saturating_mul(GLYPH_WIDTH + CHARACTER_SPACING) - CHARACTER_SPACING
+    }
+}
+
+//draws text with its top left corner in row and column, pixels out of range are not drawn.
+//Row and column can come from a schedule file, so the positions saturate instead of overflowing.
+pub fn draw_text(grid: &mut Grid, text: &str, row: i32, column: i32, color: RGB) {
+    let max_rows = grid.grid.len() as i32;
+    let max_columns = grid.grid[0].len() as i32;
+
+    for (i, character) in text.chars().enumerate() {
+        let glyph_column =
+            column.saturating_add((i as i32).saturating_mul(GLYPH_WIDTH + CHARACTER_SPACING));
+        if glyph_column >= max_columns {
+            break;
+        }
+
+        for (glyph_row, bits) in glyph(character).iter().enumerate() {
+            for x in 0..GLYPH_WIDTH {
+                if bits & (1 << (GLYPH_WIDTH - 1 - x)) == 0 {
+                    continue;
+                }
+
+                let cell_row = row.saturating_add(glyph_row as i32);
+                let cell_column = glyph_column.saturating_add(x);
+                if cell_row >= 0
+                    && cell_row < max_rows
+                    && cell_column >= 0
+                    && cell_column < max_columns
+                {
+                    grid.grid[cell_row as usize][cell_column as usize] = color;
+                    grid.opaque[cell_row as usize][cell_column as usize] = true;
+                }
+            }
+        }
+    }
+}
+
+//called by the render loop, moves the marquee one column to the left once the interval has passed
+pub fn tick(shared_marquee: &SharedMarquee, shared_grid: &SharedGrid) {
+    let mut marquee = shared_marquee.marquee.lock().expect("marquee lock failed");
+
+    if marquee.active && marquee.last_step.elapsed() >= marquee.interval {
+        let mut sharedgrid_data = shared_grid.sharedgrid.lock().expect("grid lock failed");
+        let grid_data = match layers::layer_mut(&mut sharedgrid_data, &marquee.layer) {
+            Ok(grid_data) => grid_data,
+            Err(_) => return,
+        };
+        let max_rows = grid_data.grid.len() as i32;
+        let max_columns = grid_data.grid[0].len() as i32;
+        let background = grid_data.background;
+
+        for row in marquee.row.max(0)..marquee.row.saturating_add(GLYPH_HEIGHT).min(max_rows) {
+            for column in 0..max_columns {
+                grid_data.grid[row as usize][column as usize] = background;
+                grid_data.opaque[row as usize][column as usize] = false;
+            }
+        }
+
+        draw_text(
+            grid_data,
+            &marquee.text,
+            marquee.row,
+            max_columns - marquee.offset,
+            marquee.color,
+        );
+
+        marquee.offset = marquee.offset.saturating_add(1);
+        if marquee.offset > max_columns.saturating_add(text_width(&marquee.text)) {
+            marquee.offset = 0;
+        }
+        marquee.last_step = Instant::now();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lib;
+
+    const WHITE: RGB = RGB {
+        red: 255,
+        green: 255,
+        blue: 255,
+    };
+    const BLACK: RGB = RGB {
+        red: 0,
+        green: 0,
+        blue: 0,
+    };
+
+    #[test]
+    fn text_is_cut_at_the_edges() {
+        let mut layers = layers::layers_init(8, 8, BLACK);
+        let grid = layers::layer_mut(&mut layers, &None).unwrap();
+        draw_text(grid, "I", 4, 6, WHITE);
+        assert_eq!(grid.grid[4][7], WHITE);
+        assert_eq!(grid.grid[3][7], BLACK);
+    }
+
+    #[test]
+    fn far_positions_do_not_overflow() {
+        let mut layers = layers::layers_init(8, 8, BLACK);
+        let grid = layers::layer_mut(&mut layers, &None).unwrap();
+        draw_text(grid, "HELLO", i32::max_value(), i32::max_value(), WHITE);
+        draw_text(grid, "HELLO", i32::min_value(), i32::min_value(), WHITE);
+        draw_text(grid, "HELLO", 0, i32::max_value() - 3, WHITE);
+        assert!(grid
+            .grid
+            .iter()
+            .all(|row| row.iter().all(|&cell| cell == BLACK)));
+    }
+
+    #[test]
+    fn marquees_far_down_do_not_overflow() {
+        let sharedgrid = lib::grid_init(8, 8, BLACK);
+        let shared_marquee = marquee_init();
+        {
+            let mut marquee = shared_marquee.marquee.lock().unwrap();
+            marquee.text = String::from("HI");
+            marquee.row = i32::max_value() - 2;
+            marquee.interval = Duration::from_millis(0);
+            marquee.active = true;
+        }
+        tick(&shared_marquee, &sharedgrid);
+        assert_eq!(shared_marquee.marquee.lock().unwrap().offset, 1);
+    }
+}
@@ -0,0 +1,271 @@
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use gif::SetParameter;
+
+use crate::lib::data::SharedGrid;
+use crate::lib::{err, layers};
+
+//the largest scale and the most pixels a frame of the gif may have, every frame is scaled up
+//in memory before it is encoded
+const MAX_SCALE: usize = 64;
+const MAX_FRAME_PIXELS: usize = 1024 * 1024;
+
+//what was on the display at one point in time
+#[derive(Clone)]
+struct Snapshot {
+    //seconds since the unix epoch
+    time: u64,
+    rows: usize,
+    columns: usize,
+    //rgb24, row by row
+    pixels: Vec<u8>,
+}
+
+pub struct Timelapse {
+    //the oldest snapshots are dropped when there are more
+    max_frames: usize,
+    frames: VecDeque<Snapshot>,
+}
+
+pub struct SharedTimelapse {
+    pub timelapse: Arc<Mutex<Timelapse>>,
+}
+
+//how a timelapse is rendered
+pub struct Export {
+    //seconds since the unix epoch, the whole history if not given
+    pub from: Option<u64>,
+    pub to: Option<u64>,
+    //pixels per cell
+    pub scale: usize,
+    //milliseconds per frame
+    pub delay: u64,
+}
+
+pub fn timelapse_init(max_frames: usize) -> SharedTimelapse {
+    SharedTimelapse {
+        timelapse: Arc::new(Mutex::new(Timelapse {
+            max_frames,
+            frames: VecDeque::new(),
+        })),
+    }
+}
+
+//takes a snapshot of the displayed grid every interval
+pub fn run(interval: Duration, sharedgrid: SharedGrid, shared_timelapse: SharedTimelapse) {
+    loop {
+        let snapshot = snapshot(&sharedgrid);
+
+        let mut timelapse = shared_timelapse
+            .timelapse
+            .lock()
+            .expect("timelapse lock failed");
+        if timelapse.frames.len() >= timelapse.max_frames {
+            timelapse.frames.pop_front();
+        }
+        if timelapse.max_frames > 0 {
+            timelapse.frames.push_back(snapshot);
+        }
+        drop(timelapse);
+
+        thread::sleep(interval);
+    }
+}
+
+fn snapshot(sharedgrid: &SharedGrid) -> Snapshot {
+    let sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+    let grid_data = layers::composite(&sharedgrid_data);
+    drop(sharedgrid_data);
+
+    let mut pixels = Vec::new();
+    for row in grid_data.grid.iter() {
+        for cell in row.iter() {
+            pixels.extend_from_slice(&[cell.red, cell.green, cell.blue]);
+        }
+    }
+
+    let time = match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(elapsed) => elapsed.as_secs(),
+        Err(_) => 0,
+    };
+    Snapshot {
+        time,
+        rows: grid_data.grid.len(),
+        columns: grid_data.grid[0].len(),
+        pixels,
+    }
+}
+
+//an animated gif of the snapshots within the time window, looping forever
+pub fn export_gif(shared_timelapse: &SharedTimelapse, export: &Export) -> std::io::Result<Vec<u8>> {
+    let scale = export.scale.max(1);
+    if scale > MAX_SCALE {
+        return Err(err::rejection(
+            "invalid_scale",
+            format!("The scale can be at most {}", MAX_SCALE),
+        ));
+    }
+
+    //copied, so snapshots can be taken while the gif is encoded
+    let frames: Vec<Snapshot> = shared_timelapse
+        .timelapse
+        .lock()
+        .expect("timelapse lock failed")
+        .frames
+        .iter()
+        .filter(|snapshot| export.from.map_or(true, |from| snapshot.time >= from))
+        .filter(|snapshot| export.to.map_or(true, |to| snapshot.time <= to))
+        .cloned()
+        .collect();
+
+    let first = match frames.first() {
+        Some(first) => first,
+        None => return Err(Error::new(ErrorKind::Other, "No snapshots in this time")),
+    };
+    let (width, height) = match frame_size(first, scale) {
+        Some(size) => size,
+        None => return Err(err::rejection("invalid_scale", "The scale is too large")),
+    };
+
+    let mut gif = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut gif, width as u16, height as u16, &[])?;
+        encoder.set(gif::Repeat::Infinite)?;
+
+        for snapshot in frames.iter() {
+            //the grid size doesn't change while the program runs, this is only a safeguard
+            if snapshot.rows != first.rows || snapshot.columns != first.columns {
+                continue;
+            }
+            let pixels = scale_up(snapshot, scale);
+            let mut frame = gif::Frame::from_rgb_speed(width as u16, height as u16, &pixels, 10);
+            //gif delays are in hundredths of a second
+            frame.delay = (export.delay / 10).min(u64::from(u16::max_value())) as u16;
+            encoder.write_frame(&frame)?;
+        }
+    }
+    Ok(gif)
+}
+
+//width and height of a frame in pixels, none if it would be too large for memory or the gif
+fn frame_size(snapshot: &Snapshot, scale: usize) -> Option<(usize, usize)> {
+    let width = snapshot.columns.checked_mul(scale)?;
+    let height = snapshot.rows.checked_mul(scale)?;
+    let max_side = usize::from(u16::max_value());
+
+    if width > max_side || height > max_side || width.checked_mul(height)? > MAX_FRAME_PIXELS {
+        None
+    } else {
+        Some((width, height))
+    }
+}
+
+//every cell becomes a square of scale * scale pixels
+fn scale_up(snapshot: &Snapshot, scale: usize) -> Vec<u8> {
+    let mut pixels = Vec::with_capacity(snapshot.pixels.len() * scale * scale);
+    for row in snapshot.pixels.chunks(snapshot.columns * 3) {
+        let mut scaled_row = Vec::with_capacity(row.len() * scale);
+        for cell in row.chunks(3) {
+            for _ in 0..scale {
+                scaled_row.extend_from_slice(cell);
+            }
+        }
+        for _ in 0..scale {
+            pixels.extend_from_slice(&scaled_row);
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timelapse(rows: usize, columns: usize, times: &[u64]) -> SharedTimelapse {
+        let shared_timelapse = timelapse_init(10);
+        {
+            let mut timelapse = shared_timelapse.timelapse.lock().unwrap();
+            for &time in times.iter() {
+                timelapse.frames.push_back(Snapshot {
+                    time,
+                    rows,
+                    columns,
+                    pixels: vec![time as u8; rows * columns * 3],
+                });
+            }
+        }
+        shared_timelapse
+    }
+
+    fn export(scale: usize) -> Export {
+        Export {
+            from: None,
+            to: None,
+            scale,
+            delay: 100,
+        }
+    }
+
+    #[test]
+    fn gif_has_the_scaled_size() {
+        let gif = export_gif(&timelapse(2, 3, &[1, 2]), &export(4)).unwrap();
+        assert_eq!(&gif[..6], b"GIF89a");
+        //width and height are little-endian
+        assert_eq!(&gif[6..10], &[12, 0, 8, 0]);
+    }
+
+    #[test]
+    fn cells_become_squares() {
+        let snapshot = Snapshot {
+            time: 0,
+            rows: 1,
+            columns: 2,
+            pixels: vec![1, 1, 1, 2, 2, 2],
+        };
+        let pixels = scale_up(&snapshot, 2);
+        let row = [1, 1, 1, 1, 1, 1, 2, 2, 2, 2, 2, 2];
+        assert_eq!(pixels, [row, row].concat());
+    }
+
+    #[test]
+    fn large_scales_are_rejected() {
+        let shared_timelapse = timelapse(16, 16, &[1]);
+        assert!(export_gif(&shared_timelapse, &export(MAX_SCALE)).is_ok());
+
+        let error = export_gif(&shared_timelapse, &export(MAX_SCALE + 1)).unwrap_err();
+        assert_eq!(err::reason(&error), "invalid_scale");
+        let error = export_gif(&shared_timelapse, &export(usize::max_value())).unwrap_err();
+        assert_eq!(err::reason(&error), "invalid_scale");
+    }
+
+    #[test]
+    fn frames_are_limited_in_pixels() {
+        let snapshot = |rows, columns| Snapshot {
+            time: 0,
+            rows,
+            columns,
+            pixels: Vec::new(),
+        };
+        assert_eq!(frame_size(&snapshot(16, 16), 64), Some((1024, 1024)));
+        assert_eq!(frame_size(&snapshot(17, 16), 64), None);
+        assert_eq!(frame_size(&snapshot(1, 70_000), 1), None);
+        assert_eq!(frame_size(&snapshot(usize::max_value(), 1), 2), None);
+    }
+
+    #[test]
+    fn only_snapshots_in_the_window_are_exported() {
+        let shared_timelapse = timelapse(1, 1, &[10, 20]);
+        let window = |from, to| Export {
+            from,
+            to,
+            ..export(1)
+        };
+        assert!(export_gif(&shared_timelapse, &window(Some(15), None)).is_ok());
+        assert!(export_gif(&shared_timelapse, &window(Some(21), None)).is_err());
+        assert!(export_gif(&shared_timelapse, &window(None, Some(9))).is_err());
+    }
+}
@@ -0,0 +1,79 @@
+use std::io::{Error, ErrorKind};
+use std::net::UdpSocket;
+
+use crate::lib::blend::{self, BlendMode};
+use crate::lib::data::{SharedGrid, RGB};
+use crate::lib::{err, layers, palette, regions, wire};
+
+//the first byte of a packet is its type
+pub const CELLS: u8 = 1;
+pub const PATCH: u8 = 2;
+
+//row and column as big-endian 16 bit numbers, then red, green and blue
+const CELL_LENGTH: usize = 7;
+
+//the largest payload a udp datagram can carry
+pub const MAX_PACKET: usize = 65507;
+
+//receives packets on the port until the socket fails, everything is drawn on the content layer
+pub fn listen(port: u16, max_packet: usize, sharedgrid: SharedGrid) -> std::io::Result<()> {
+    let socket = UdpSocket::bind(("0.0.0.0", port))?;
+    println!("UDP listener on port {}", port);
+
+    //one byte more than allowed, so packets that are too large can be told apart
+    let mut buffer = vec![0; max_packet + 1];
+    loop {
+        let (length, _source) = socket.recv_from(&mut buffer)?;
+        if length > max_packet {
+            continue;
+        }
+
+        //there is no one to answer, packets that can not be read are dropped
+        let _ = apply_packet(&buffer[..length], &sharedgrid);
+    }
+}
+
+pub fn apply_packet(packet: &[u8], sharedgrid: &SharedGrid) -> std::io::Result<()> {
+    match packet.split_first() {
+        Some((&CELLS, cells)) => apply_cells(cells, sharedgrid),
+        Some((&PATCH, patch)) => {
+            let raw = wire::parse_binary(patch)?;
+            let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+            let grid_data = layers::layer_mut(&mut sharedgrid_data, &None)?;
+            wire::write_rgb24(grid_data, &raw, &BlendMode::Normal, &regions::everywhere())
+        }
+        _ => Err(Error::new(ErrorKind::Other, "Unknown packet type")),
+    }
+}
+
+//cells that are out of range are skipped, the rest of the packet is still drawn
+fn apply_cells(cells: &[u8], sharedgrid: &SharedGrid) -> std::io::Result<()> {
+    if cells.len() % CELL_LENGTH != 0 {
+        return Err(Error::new(
+            ErrorKind::Other,
+            "Cell packets must be made of 7 byte cells",
+        ));
+    }
+
+    let mut sharedgrid_data = sharedgrid.sharedgrid.lock().expect("grid lock failed");
+    let grid_data = layers::layer_mut(&mut sharedgrid_data, &None)?;
+    let max_rows = &grid_data.grid.len();
+    let max_columns = &grid_data.grid[0].len();
+
+    for cell in cells.chunks(CELL_LENGTH) {
+        let row = i32::from(wire::read_u16(cell, 0));
+        let column = i32::from(wire::read_u16(cell, 2));
+        let color = RGB {
+            red: cell[4],
+            green: cell[5],
+            blue: cell[6],
+        };
+
+        if err::is_position_in_range(row, column, max_rows, max_columns).is_ok() {
+            let (row, column) = (row as usize, column as usize);
+            blend::paint(grid_data, row, column, color, 255, &BlendMode::Normal);
+            palette::mark(grid_data, row, column, None);
+        }
+    }
+    Ok(())
+}
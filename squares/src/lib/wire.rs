@@ -0,0 +1,318 @@
+use std::io::{Error, Read};
+
+use crate::lib::blend::{self, BlendMode};
+use crate::lib::data::{Grid, RGB};
+use crate::lib::{err, palette};
+use crate::lib::regions::{self, Area};
+
+//row and column offset as signed, width and height as unsigned 16 bit big-endian numbers
+pub const HEADER_LENGTH: usize = 8;
+
+//the largest body that is read, enough for a 1024x1024 grid
+pub const MAX_LENGTH: usize = HEADER_LENGTH + 1024 * 1024 * 3;
+
+//rows of 3 bytes per cell, red, green and blue
+pub struct RawGrid<'a> {
+    pub zero_row: i32,
+    pub zero_column: i32,
+    pub width: usize,
+    pub height: usize,
+    pub pixels: &'a [u8],
+}
+
+//a big-endian 16 bit number at position i
+pub fn read_u16(bytes: &[u8], i: usize) -> u16 {
+    u16::from(bytes[i]) << 8 | u16::from(bytes[i + 1])
+}
+
+pub fn read_body<R: Read>(body: R) -> std::io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    body.take(MAX_LENGTH as u64 + 1).read_to_end(&mut bytes)?;
+
+    if bytes.len() > MAX_LENGTH {
+        return Err(err::rejection("invalid_grid", "Grid data is too large"));
+    }
+    Ok(bytes)
+}
+
+//reads the header of a binary grid, the rest of the bytes are the pixels
+pub fn parse_binary(bytes: &[u8]) -> std::io::Result<RawGrid> {
+    if bytes.len() < HEADER_LENGTH {
+        return Err(err::rejection("invalid_grid", "Grid header is missing"));
+    }
+    let raw = RawGrid {
+        zero_row: i32::from(read_u16(bytes, 0) as i16),
+        zero_column: i32::from(read_u16(bytes, 2) as i16),
+        width: read_u16(bytes, 4) as usize,
+        height: read_u16(bytes, 6) as usize,
+        pixels: &bytes[HEADER_LENGTH..],
+    };
+    check_size(&raw)?;
+    Ok(raw)
+}
+
+//the base64 variant only has a width, the height follows from the number of bytes
+pub fn parse_base64(
+    zero_row: i32,
+    zero_column: i32,
+    width: usize,
+    pixels: &[u8],
+) -> std::io::Result<RawGrid> {
+    if width == 0 {
+        return Err(err::rejection("invalid_grid", "Grid width can not be 0"));
+    }
+
+    let raw = RawGrid {
+        zero_row,
+        zero_column,
+        width,
+        height: pixels.len() / row_length(width)?,
+        pixels,
+    };
+    check_size(&raw)?;
+    Ok(raw)
+}
+
+pub fn decode_base64(data: &str) -> std::io::Result<Vec<u8>> {
+    match base64::decode(data) {
+        Ok(bytes) => Ok(bytes),
+        Err(error) => Err(err::rejection(
+            "invalid_grid",
+            format!("Invalid base64 data: {}", error),
+        )),
+    }
+}
+
+fn check_size(raw: &RawGrid) -> std::io::Result<()> {
+    if raw.width == 0 || raw.height == 0 {
+        return Err(err::rejection("invalid_grid", "Grid size can not be 0"));
+    }
+
+    let expected = match row_length(raw.width)?.checked_mul(raw.height) {
+        Some(expected) => expected,
+        None => return Err(too_large()),
+    };
+    if raw.pixels.len() != expected {
+        return Err(err::rejection(
+            "invalid_grid",
+            format!(
+                "Grid data has {} bytes, {}x{} cells need {}",
+                raw.pixels.len(),
+                raw.width,
+                raw.height,
+                expected
+            ),
+        ));
+    }
+    Ok(())
+}
+
+//bytes per row of pixels, the width comes from the client and may be anything
+fn row_length(width: usize) -> std::io::Result<usize> {
+    match width.checked_mul(3) {
+        Some(length) => Ok(length),
+        None => Err(too_large()),
+    }
+}
+
+fn too_large() -> Error {
+    err::rejection("invalid_grid", "Grid size is too large")
+}
+
+//writes the pixels row by row into the grid, cells outside of the grid or the area are skipped
+pub fn write_rgb24(
+    grid: &mut Grid,
+    raw: &RawGrid,
+    mode: &BlendMode,
+    area: &Area,
+) -> std::io::Result<()> {
+    let max_rows = grid.grid.len() as i32;
+    let max_columns = grid.grid[0].len() as i32;
+
+    let cells = (0..raw.height as i32).flat_map(|i| (0..raw.width as i32).map(move |j| (i, j)));
+    //positions past the end of i32 are not on the grid anyway
+    let positions = cells.map(|(i, j)| {
+        (
+            raw.zero_row.saturating_add(i),
+            raw.zero_column.saturating_add(j),
+        )
+    });
+    regions::check_cells(area, positions, max_rows as usize, max_columns as usize)?;
+
+    for (i, row) in raw.pixels.chunks(row_length(raw.width)?).enumerate() {
+        let cell_row = raw.zero_row.saturating_add(i as i32);
+        if cell_row < 0 || cell_row >= max_rows {
+            continue;
+        }
+
+        for (j, pixel) in row.chunks(3).enumerate() {
+            let cell_column = raw.zero_column.saturating_add(j as i32);
+            if cell_column < 0
+                || cell_column >= max_columns
+                || !regions::contains(area, cell_row, cell_column)
+            {
+                continue;
+            }
+
+            let color = RGB {
+                red: pixel[0],
+                green: pixel[1],
+                blue: pixel[2],
+            };
+            let (cell_row, cell_column) = (cell_row as usize, cell_column as usize);
+            blend::paint(grid, cell_row, cell_column, color, 255, mode);
+            palette::mark(grid, cell_row, cell_column, None);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    use crate::lib::layers;
+
+    const BLACK: RGB = RGB {
+        red: 0,
+        green: 0,
+        blue: 0,
+    };
+
+    fn header(zero_row: i16, zero_column: i16, width: u16, height: u16) -> Vec<u8> {
+        [zero_row as u16, zero_column as u16, width, height]
+            .iter()
+            .flat_map(|value| vec![(value >> 8) as u8, *value as u8])
+            .collect()
+    }
+
+    fn gray(value: u8) -> RGB {
+        RGB {
+            red: value,
+            green: value,
+            blue: value,
+        }
+    }
+
+    fn grid(rows: i32, columns: i32) -> Grid {
+        layers::new_layer("test", 0, columns, rows, BLACK).grid
+    }
+
+    #[test]
+    fn header_is_big_endian() {
+        let mut bytes = header(0x0102, 0x0304, 2, 1);
+        bytes.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+        let raw = parse_binary(&bytes).unwrap();
+        assert_eq!((raw.zero_row, raw.zero_column), (0x0102, 0x0304));
+        assert_eq!((raw.width, raw.height), (2, 1));
+        assert_eq!(raw.pixels, &[1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn offsets_are_signed() {
+        let mut bytes = header(-1, -300, 1, 1);
+        bytes.extend_from_slice(&[1, 2, 3]);
+        let raw = parse_binary(&bytes).unwrap();
+        assert_eq!((raw.zero_row, raw.zero_column), (-1, -300));
+    }
+
+    #[test]
+    fn short_headers_are_rejected() {
+        let error = parse_binary(&header(0, 0, 1, 1)[..7]).err().unwrap();
+        assert_eq!(error.to_string(), "Grid header is missing");
+        assert_eq!(err::reason(&error), "invalid_grid");
+        assert!(parse_binary(&[]).is_err());
+    }
+
+    #[test]
+    fn pixels_must_match_the_size() {
+        let mut bytes = header(0, 0, 2, 2);
+        bytes.extend_from_slice(&[0; 11]);
+        let error = parse_binary(&bytes).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Grid data has 11 bytes, 2x2 cells need 12"
+        );
+        assert_eq!(err::reason(&error), "invalid_grid");
+
+        bytes.extend_from_slice(&[0; 2]);
+        assert!(parse_binary(&bytes).is_err());
+    }
+
+    #[test]
+    fn empty_grids_are_rejected() {
+        assert!(parse_binary(&header(0, 0, 0, 1)).is_err());
+        assert!(parse_binary(&header(0, 0, 1, 0)).is_err());
+    }
+
+    #[test]
+    fn base64_height_follows_from_the_data() {
+        let pixels = [0; 12];
+        let raw = parse_base64(1, 2, 2, &pixels).unwrap();
+        assert_eq!((raw.width, raw.height), (2, 2));
+
+        assert!(parse_base64(0, 0, 0, &pixels).is_err());
+        assert!(parse_base64(0, 0, 5, &pixels).is_err());
+        assert!(parse_base64(0, 0, 3, &pixels[..10]).is_err());
+    }
+
+    #[test]
+    fn huge_widths_are_rejected() {
+        let error = parse_base64(0, 0, usize::max_value(), &[0; 3])
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "Grid size is too large");
+        assert_eq!(err::reason(&error), "invalid_grid");
+    }
+
+    #[test]
+    fn invalid_base64_is_rejected() {
+        assert_eq!(decode_base64("AQID").unwrap(), vec![1, 2, 3]);
+        let error = decode_base64("AQI!").err().unwrap();
+        assert_eq!(err::reason(&error), "invalid_grid");
+    }
+
+    #[test]
+    fn bodies_are_read_up_to_the_largest_grid() {
+        let body = read_body(Cursor::new(vec![0; MAX_LENGTH])).unwrap();
+        assert_eq!(body.len(), MAX_LENGTH);
+        assert!(read_body(Cursor::new(vec![0; MAX_LENGTH + 1])).is_err());
+    }
+
+    #[test]
+    fn cells_outside_of_the_grid_are_skipped() {
+        let mut grid = grid(2, 2);
+        let pixels: Vec<u8> = (1..=9).flat_map(|value| vec![value; 3]).collect();
+        let raw = parse_base64(-1, -1, 3, &pixels).unwrap();
+        write_rgb24(&mut grid, &raw, &BlendMode::Normal, &regions::everywhere()).unwrap();
+
+        assert_eq!(grid.grid[0], vec![gray(5), gray(6)]);
+        assert_eq!(grid.grid[1], vec![gray(8), gray(9)]);
+        assert!(grid
+            .opaque
+            .iter()
+            .all(|row| row.iter().all(|&opaque| opaque)));
+        assert!(grid
+            .opaque
+            .iter()
+            .all(|row| row.iter().all(|&opaque| opaque)));
+    }
+
+    #[test]
+    fn offsets_far_from_the_grid_do_not_overflow() {
+        let mut grid = grid(2, 2);
+        let raw = RawGrid {
+            zero_row: i32::max_value(),
+            zero_column: i32::max_value(),
+            width: 2,
+            height: 2,
+            pixels: &[1; 12],
+        };
+        write_rgb24(&mut grid, &raw, &BlendMode::Normal, &regions::everywhere()).unwrap();
+        assert!(grid
+            .grid
+            .iter()
+            .all(|row| row.iter().all(|&cell| cell == BLACK)));
+    }
+}
@@ -17,7 +17,17 @@ use sdl2::keyboard::Keycode;
 
 pub mod lib;
 
+use lib::audit::SharedAudit;
+use lib::automata::SharedAutomaton;
 use lib::data::SharedGrid;
+use lib::events::SharedEvents;
+use lib::intervention::SharedIntervention;
+use lib::metrics::{RequestCounter, SharedMetrics};
+use lib::palette::SharedPalettes;
+use lib::schedule::SharedSchedule;
+use lib::shutdown::{RequestTracker, SharedShutdown};
+use lib::text::SharedMarquee;
+use lib::timelapse::SharedTimelapse;
 use lib::requests;
 
 use structopt::StructOpt;
@@ -27,6 +37,132 @@ use structopt::StructOpt;
 struct CommandLineArgs {
     rows: i32,
     columns: i32,
+    /// Background color as red,green,blue
+    #[structopt(
+        long = "background",
+        default_value = "35,15,13",
+        parse(try_from_str = "lib::parse_rgb")
+    )]
+    background: lib::data::RGB,
+    /// Pattern shown on start instead of a blank grid, e.g. plasma or test_card
+    #[structopt(long = "pattern")]
+    pattern: Option<String>,
+    /// Palette file (.gpl or .hex) that is active on start instead of the CGA colors
+    #[structopt(long = "palette", parse(from_os_str))]
+    palette: Option<std::path::PathBuf>,
+    /// Port of the UDP pixel listener, it only runs if a port is given
+    #[structopt(long = "udp-port")]
+    udp_port: Option<u16>,
+    /// Largest UDP packet in bytes that is accepted, larger packets are dropped
+    #[structopt(long = "udp-max-packet", default_value = "65507")]
+    udp_max_packet: usize,
+    /// Port of the Pixelflut server, it only runs if a port is given
+    #[structopt(long = "pixelflut-port")]
+    pixelflut_port: Option<u16>,
+    /// Receive Art-Net on UDP port 6454
+    #[structopt(long = "artnet")]
+    artnet: bool,
+    /// Receive E1.31 (sACN) on UDP port 5568
+    #[structopt(long = "sacn")]
+    sacn: bool,
+    /// How DMX channels are mapped to cells, row-major or serpentine
+    #[structopt(
+        long = "dmx-layout",
+        default_value = "row-major",
+        parse(try_from_str = "lib::dmx::layout_from_name")
+    )]
+    dmx_layout: lib::dmx::Layout,
+    /// Universe that starts at the first cell, the following universes continue after it
+    #[structopt(long = "dmx-universe", default_value = "0")]
+    dmx_universe: u16,
+    /// Cells per universe, 3 channels each
+    #[structopt(long = "dmx-cells-per-universe", default_value = "170")]
+    dmx_cells_per_universe: usize,
+    /// First cell of a universe as <universe>:<cell>, can be given several times
+    #[structopt(long = "dmx-offset", parse(try_from_str = "lib::dmx::parse_offset"))]
+    dmx_offset: Vec<(u16, usize)>,
+    /// LED matrix the grid is sent to: opc:<host>:<port>, tpm2:<device> or adalight:<device>
+    #[structopt(long = "output", parse(try_from_str = "lib::output::parse_sink"))]
+    output: Vec<lib::output::Sink>,
+    /// How the LEDs are wired, row-major or serpentine
+    #[structopt(
+        long = "output-layout",
+        default_value = "row-major",
+        parse(try_from_str = "lib::dmx::layout_from_name")
+    )]
+    output_layout: lib::dmx::Layout,
+    /// Clockwise rotation of the grid on the LED matrix: 0, 90, 180 or 270
+    #[structopt(
+        long = "output-rotation",
+        default_value = "0",
+        parse(try_from_str = "lib::output::parse_rotation")
+    )]
+    output_rotation: u16,
+    /// Mirror the grid left to right on the LED matrix
+    #[structopt(long = "output-flip-horizontal")]
+    output_flip_horizontal: bool,
+    /// Mirror the grid top to bottom on the LED matrix
+    #[structopt(long = "output-flip-vertical")]
+    output_flip_vertical: bool,
+    /// Gamma correction of the LED colors, 1.0 sends the colors unchanged
+    #[structopt(long = "output-gamma", default_value = "1.0")]
+    output_gamma: f32,
+    /// Frames per second sent to the LED matrices
+    #[structopt(long = "output-fps", default_value = "20")]
+    output_fps: u64,
+    /// MQTT broker as host:port, the MQTT client only runs if a broker is given
+    #[structopt(long = "mqtt")]
+    mqtt: Option<String>,
+    /// Topic to subscribe to, can be given several times. Defaults to squares/+
+    #[structopt(long = "mqtt-topic")]
+    mqtt_topic: Vec<String>,
+    /// Topic grid changes are published to
+    #[structopt(long = "mqtt-events-topic", default_value = "squares/events")]
+    mqtt_events_topic: String,
+    /// Client id used to connect to the MQTT broker
+    #[structopt(long = "mqtt-client-id", default_value = "squares")]
+    mqtt_client_id: String,
+    /// File with API tokens, one "<token> <read|draw|admin> [name]" per line. Without it
+    /// every request is allowed
+    #[structopt(long = "tokens", parse(from_os_str))]
+    tokens: Option<std::path::PathBuf>,
+    /// Requests per second a client may send, unlimited if not given
+    #[structopt(long = "limit-requests")]
+    limit_requests: Option<f64>,
+    /// Cells per second a client may draw, unlimited if not given
+    #[structopt(long = "limit-cells")]
+    limit_cells: Option<f64>,
+    /// Share the limits between all active clients instead of giving them to every client
+    #[structopt(long = "fair-share")]
+    fair_share: bool,
+    /// What happens to writes that leave the client's region: reject or clip
+    #[structopt(
+        long = "region-mode",
+        default_value = "reject",
+        parse(try_from_str = "lib::regions::region_mode_from_name")
+    )]
+    region_mode: lib::regions::RegionMode,
+    /// JSONL file that every change of the grid is logged to, with time and client
+    #[structopt(long = "audit-log", parse(from_os_str))]
+    audit_log: Option<std::path::PathBuf>,
+    /// Size in megabytes at which the audit log is rotated
+    #[structopt(long = "audit-log-size", default_value = "10")]
+    audit_log_size: u64,
+    /// How many rotated audit logs are kept
+    #[structopt(long = "audit-log-keep", default_value = "5")]
+    audit_log_keep: usize,
+    /// JSONL file that every accepted drawing is recorded to, for squares_test's replay
+    #[structopt(long = "record", parse(from_os_str))]
+    record: Option<std::path::PathBuf>,
+    /// Seconds between the snapshots of the timelapse, no snapshots are taken if not given
+    #[structopt(long = "timelapse-interval")]
+    timelapse_interval: Option<u64>,
+    /// How many snapshots are kept, the oldest are dropped first
+    #[structopt(long = "timelapse-frames", default_value = "1440")]
+    timelapse_frames: usize,
+    /// JSON file with the scenes of the playlist and the scenes at fixed times
+    #[structopt(long = "schedule", parse(from_os_str))]
+    schedule: Option<std::path::PathBuf>,
 }
 
 fn main() {
@@ -40,37 +176,425 @@ fn main() {
 
     let (mut canvas, mut events) = lib::init(canvas_width, canvas_height);
 
-    let shared_grid = lib::grid_init(args.columns, args.rows);
+    let shared_grid = lib::grid_init(args.columns, args.rows, args.background);
+    if let Some(name) = &args.pattern {
+        match lib::pattern::pattern_from_name(name) {
+            Ok(pattern) => {
+                let mut sharedgrid_data = shared_grid.sharedgrid.lock().expect("grid lock failed");
+                let grid_data = lib::layers::layer_mut(&mut sharedgrid_data, &None)
+                    .expect("content layer exists");
+                let params = lib::pattern::default_params(grid_data);
+                lib::pattern::fill_grid(grid_data, &pattern, &params);
+            }
+            Err(error) => println!("{}", error),
+        }
+    }
     let sharedgrid_rocket = SharedGrid {
         sharedgrid: shared_grid.sharedgrid.clone(),
     };
 
+    let shared_automaton = lib::automata::automaton_init();
+    let automaton_rocket = SharedAutomaton {
+        automaton: shared_automaton.automaton.clone(),
+    };
+
+    let shared_palettes = lib::palette::palettes_init();
+    if let Some(path) = &args.palette {
+        match lib::palette::load_palette(path) {
+            Ok(palette) => {
+                let mut palettes = shared_palettes.palettes.lock().expect("palette lock failed");
+                let name = palette.name.clone();
+                match lib::palette::add_palette(&mut palettes, palette) {
+                    Ok(()) => {
+                        palettes.active = palettes
+                            .palettes
+                            .iter()
+                            .position(|palette| palette.name == name)
+                            .expect("palette was added");
+                    }
+                    Err(error) => println!("{}", error),
+                }
+            }
+            Err(error) => println!("{}", error),
+        }
+    }
+    let palettes_rocket = SharedPalettes {
+        palettes: shared_palettes.palettes.clone(),
+    };
+
+    let tokens = match &args.tokens {
+        Some(path) => match lib::auth::load_tokens(path) {
+            Ok(tokens) => tokens,
+            Err(error) => {
+                //starting without the tokens would leave the grid open to everyone
+                println!("Could not load tokens: {}", error);
+                return;
+            }
+        },
+        None => lib::auth::no_tokens(),
+    };
+
+    let limits_rocket = lib::limits::limits_init(lib::limits::LimitSettings {
+        requests_per_second: args.limit_requests,
+        cells_per_second: args.limit_cells,
+        fair_share: args.fair_share,
+    });
+
+    let regions_rocket = lib::regions::regions_init(args.region_mode);
+
+    let shared_audit = match lib::audit::audit_init(lib::audit::AuditSettings {
+        path: args.audit_log.clone(),
+        max_bytes: args.audit_log_size * 1024 * 1024,
+        keep: args.audit_log_keep,
+    }) {
+        Ok(audit) => audit,
+        Err(error) => {
+            println!("Could not open the audit log: {}", error);
+            return;
+        }
+    };
+    let audit_rocket = SharedAudit {
+        audit: shared_audit.audit.clone(),
+    };
+
+    let shared_events = lib::events::events_init();
+    let events_rocket = SharedEvents {
+        subscribers: shared_events.subscribers.clone(),
+    };
+
+    let shared_marquee = lib::text::marquee_init();
+    let marquee_rocket = SharedMarquee {
+        marquee: shared_marquee.marquee.clone(),
+    };
+
+    if let Some(port) = args.udp_port {
+        let sharedgrid_udp = SharedGrid {
+            sharedgrid: shared_grid.sharedgrid.clone(),
+        };
+        let max_packet = args.udp_max_packet.min(lib::udp::MAX_PACKET);
+        thread::spawn(move || {
+            //pixels over udp
+            if let Err(error) = lib::udp::listen(port, max_packet, sharedgrid_udp) {
+                println!("UDP listener stopped: {}", error);
+            }
+        });
+    }
+
+    if let Some(port) = args.pixelflut_port {
+        let sharedgrid_pixelflut = SharedGrid {
+            sharedgrid: shared_grid.sharedgrid.clone(),
+        };
+        thread::spawn(move || {
+            //pixelflut clients over tcp
+            if let Err(error) = lib::pixelflut::serve(port, sharedgrid_pixelflut) {
+                println!("Pixelflut server stopped: {}", error);
+            }
+        });
+    }
+
+    let mapping = lib::dmx::Mapping {
+        layout: args.dmx_layout,
+        rows: args.rows as usize,
+        columns: args.columns as usize,
+        first_universe: args.dmx_universe,
+        cells_per_universe: args.dmx_cells_per_universe.max(1).min(lib::dmx::CELLS_PER_UNIVERSE),
+        offsets: args.dmx_offset.clone(),
+    };
+    if args.artnet || args.sacn {
+        if let Err(error) = lib::dmx::check_mapping(&mapping) {
+            println!("Could not map the dmx universes: {}", error);
+            return;
+        }
+    }
+    if args.artnet {
+        let sharedgrid_artnet = SharedGrid {
+            sharedgrid: shared_grid.sharedgrid.clone(),
+        };
+        let mapping = mapping.clone();
+        thread::spawn(move || {
+            //dmx from lighting consoles
+            if let Err(error) = lib::dmx::listen_artnet(mapping, sharedgrid_artnet) {
+                println!("Art-Net receiver stopped: {}", error);
+            }
+        });
+    }
+    if args.sacn {
+        let sharedgrid_sacn = SharedGrid {
+            sharedgrid: shared_grid.sharedgrid.clone(),
+        };
+        thread::spawn(move || {
+            if let Err(error) = lib::dmx::listen_e131(mapping, sharedgrid_sacn) {
+                println!("E1.31 receiver stopped: {}", error);
+            }
+        });
+    }
+
+    let output_mapping = lib::output::OutputMapping {
+        layout: args.output_layout,
+        rotation: args.output_rotation,
+        flip_horizontal: args.output_flip_horizontal,
+        flip_vertical: args.output_flip_vertical,
+        gamma: args.output_gamma,
+    };
+    let output_interval = time::Duration::from_millis(1000 / args.output_fps.max(1));
+    for sink in args.output.iter() {
+        let sharedgrid_output = SharedGrid {
+            sharedgrid: shared_grid.sharedgrid.clone(),
+        };
+        let sink = sink.clone();
+        let mapping = output_mapping.clone();
+        thread::spawn(move || {
+            //every led matrix gets its own thread, so a slow one doesn't hold up the others
+            lib::output::run(sink, mapping, output_interval, sharedgrid_output);
+        });
+    }
+
+    if let Some(broker) = &args.mqtt {
+        let topics = if args.mqtt_topic.is_empty() {
+            vec![String::from("squares/+")]
+        } else {
+            args.mqtt_topic.clone()
+        };
+        let settings = lib::mqtt::MqttSettings {
+            broker: broker.clone(),
+            client_id: args.mqtt_client_id.clone(),
+            topics,
+            events_topic: args.mqtt_events_topic.clone(),
+        };
+        let sharedgrid_mqtt = SharedGrid {
+            sharedgrid: shared_grid.sharedgrid.clone(),
+        };
+        let palettes_mqtt = SharedPalettes {
+            palettes: shared_palettes.palettes.clone(),
+        };
+        let events_mqtt = SharedEvents {
+            subscribers: shared_events.subscribers.clone(),
+        };
+        thread::spawn(move || {
+            //cells, lines and grids from mqtt devices
+            lib::mqtt::run(settings, sharedgrid_mqtt, palettes_mqtt, events_mqtt);
+        });
+    }
+
+    let scenes = match &args.schedule {
+        Some(path) => match lib::schedule::load_schedule(path) {
+            Ok(scenes) => scenes,
+            Err(error) => {
+                println!("Could not load the schedule: {}", error);
+                return;
+            }
+        },
+        None => Vec::new(),
+    };
+    let shared_schedule = match lib::schedule::schedule_init(scenes) {
+        Ok(shared_schedule) => shared_schedule,
+        Err(error) => {
+            println!("Could not load the schedule: {}", error);
+            return;
+        }
+    };
+    let schedule_rocket = SharedSchedule {
+        schedule: shared_schedule.schedule.clone(),
+    };
+    if args.schedule.is_some() {
+        let paused_schedule = program_paused.clone();
+        let sharedgrid_schedule = SharedGrid {
+            sharedgrid: shared_grid.sharedgrid.clone(),
+        };
+        let palettes_schedule = SharedPalettes {
+            palettes: shared_palettes.palettes.clone(),
+        };
+        let automaton_schedule = SharedAutomaton {
+            automaton: shared_automaton.automaton.clone(),
+        };
+        let marquee_schedule = SharedMarquee {
+            marquee: shared_marquee.marquee.clone(),
+        };
+        thread::spawn(move || {
+            //scenes of the playlist and at fixed times
+            lib::schedule::run(
+                shared_schedule,
+                paused_schedule,
+                sharedgrid_schedule,
+                palettes_schedule,
+                automaton_schedule,
+                marquee_schedule,
+            );
+        });
+    }
+
+    let shared_intervention = lib::intervention::intervention_init();
+    let intervention_rocket = SharedIntervention {
+        intervention: shared_intervention.intervention.clone(),
+    };
+
+    let shared_metrics = lib::metrics::metrics_init();
+    let metrics_rocket = SharedMetrics {
+        metrics: shared_metrics.metrics.clone(),
+    };
+    let request_counter = RequestCounter {
+        metrics: SharedMetrics {
+            metrics: shared_metrics.metrics.clone(),
+        },
+    };
+
+    let shared_timelapse = lib::timelapse::timelapse_init(args.timelapse_frames);
+    let timelapse_rocket = SharedTimelapse {
+        timelapse: shared_timelapse.timelapse.clone(),
+    };
+    if let Some(seconds) = args.timelapse_interval {
+        let sharedgrid_timelapse = SharedGrid {
+            sharedgrid: shared_grid.sharedgrid.clone(),
+        };
+        let interval = time::Duration::from_secs(seconds.max(1));
+        thread::spawn(move || {
+            lib::timelapse::run(interval, sharedgrid_timelapse, shared_timelapse);
+        });
+    }
+
+    //joined on shutdown, so the last drawings end up in the recording
+    let recorder = args.record.clone().map(|path| {
+        let receiver = lib::events::subscribe(&shared_events);
+        thread::spawn(move || {
+            if let Err(error) = lib::recorder::record(&path, receiver) {
+                println!("Recording stopped: {}", error);
+            }
+        })
+    });
+
+    let shared_shutdown = lib::shutdown::shutdown_init();
+    let shutdown_rocket = SharedShutdown {
+        shutdown: shared_shutdown.shutdown.clone(),
+    };
+    let shutdown_signals = SharedShutdown {
+        shutdown: shared_shutdown.shutdown.clone(),
+    };
+    let request_tracker = RequestTracker {
+        shutdown: SharedShutdown {
+            shutdown: shared_shutdown.shutdown.clone(),
+        },
+    };
+    thread::spawn(move || {
+        if let Err(error) = lib::shutdown::listen_signals(shutdown_signals) {
+            println!("Signals are not handled: {}", error);
+        }
+    });
+
     thread::spawn(|| {
         //http requests
         //if no data is comming over http, init color is drawn
         rocket::ignite()
-            .mount("/grid", routes![requests::new_grid])
+            .mount("/grid", routes![requests::new_grid, requests::new_grid_binary])
             .mount("/cell", routes![requests::add_cell])
             .mount("/line", routes![requests::add_line])
-            .mount("/", routes![requests::intervention])
+            .mount("/cells", routes![requests::cell_history])
+            .mount("/timelapse", routes![requests::timelapse])
+            .mount("/status", routes![requests::status])
+            .mount("/metrics", routes![requests::prometheus_metrics])
+            .mount("/shutdown", routes![requests::shutdown])
+            .mount(
+                "/schedule",
+                routes![
+                    requests::schedule_status,
+                    requests::schedule_start,
+                    requests::schedule_stop,
+                    requests::schedule_skip
+                ],
+            )
+            .mount("/clear", routes![requests::clear])
+            .mount("/pattern", routes![requests::pattern])
+            .mount(
+                "/automaton",
+                routes![
+                    requests::automaton_start,
+                    requests::automaton_stop,
+                    requests::automaton_step,
+                    requests::automaton_speed
+                ],
+            )
+            .mount("/text", routes![requests::text, requests::text_stop])
+            .mount(
+                "/layers",
+                routes![
+                    requests::list_layers,
+                    requests::set_layer,
+                    requests::delete_layer
+                ],
+            )
+            .mount(
+                "/palette",
+                routes![
+                    requests::list_palettes,
+                    requests::upload_palette,
+                    requests::load_palette,
+                    requests::activate_palette,
+                    requests::cycle_palette
+                ],
+            )
+            .mount(
+                "/regions",
+                routes![
+                    requests::list_regions,
+                    requests::add_region,
+                    requests::delete_regions
+                ],
+            )
+            .mount(
+                "/intervention",
+                routes![
+                    requests::intervention,
+                    requests::intervention_resume,
+                    requests::intervention_status
+                ],
+            )
             .manage(sharedgrid_rocket)
             .manage(program_paused_state)
+            .manage(automaton_rocket)
+            .manage(marquee_rocket)
+            .manage(palettes_rocket)
+            .manage(events_rocket)
+            .manage(tokens)
+            .manage(limits_rocket)
+            .manage(regions_rocket)
+            .manage(audit_rocket)
+            .manage(timelapse_rocket)
+            .manage(schedule_rocket)
+            .manage(intervention_rocket)
+            .manage(metrics_rocket)
+            .manage(shutdown_rocket)
+            .attach(request_counter)
+            .attach(request_tracker)
+            .register(catchers![
+                requests::unauthorized,
+                requests::forbidden,
+                requests::too_many_requests,
+                requests::shutting_down
+            ])
             .launch();
     });
 
     //video loop
     'running: loop {
+        if lib::shutdown::is_requested(&shared_shutdown) {
+            break 'running;
+        }
         let mut sharedgrid_loop = SharedGrid {
             sharedgrid: shared_grid.sharedgrid.clone(),
         };
         //
         for event in events.poll_iter() {
             match event {
-                Event::Quit { .. }
-                | Event::KeyDown {
+                Event::Quit { .. } => {
+                    lib::shutdown::request(&shared_shutdown, "window closed", 0);
+                    break 'running;
+                }
+                Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
-                } => break 'running,
+                } => {
+                    lib::shutdown::request(&shared_shutdown, "Escape", 0);
+                    break 'running;
+                }
                 Event::KeyDown {
                     keycode: Some(Keycode::Space),
                     ..
@@ -104,15 +628,33 @@ fn main() {
             }
         }
 
-        if program_paused.load(Ordering::Relaxed) == false {
-            lib::display_frame(
+        lib::intervention::tick(&shared_intervention, &sharedgrid_loop, &program_paused);
+
+        //a pause freezes the display, only an intervention is still shown while paused
+        let running = program_paused.load(Ordering::Relaxed) == false;
+        if running {
+            lib::automata::tick(&shared_automaton, &sharedgrid_loop);
+            lib::text::tick(&shared_marquee, &sharedgrid_loop);
+        }
+        if running || lib::intervention::active(&shared_intervention) {
+            let rendering = time::Instant::now();
+            let (grid_data, lock_wait) = lib::display_frame(
                 &mut canvas,
                 &sharedgrid_loop,
                 &args.columns,
                 &args.rows,
                 &cell_width,
             );
+            lib::metrics::frame(&shared_metrics, grid_data, lock_wait, rendering.elapsed());
             thread::sleep(time::Duration::from_millis(50));
         }
     }
+
+    let code = lib::shutdown::finish(
+        &shared_shutdown,
+        &shared_audit,
+        &shared_events,
+        recorder,
+    );
+    std::process::exit(code);
 }
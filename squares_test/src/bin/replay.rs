@@ -0,0 +1,100 @@
+#[macro_use]
+extern crate serde_derive;
+extern crate reqwest;
+extern crate serde_json;
+extern crate structopt;
+
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::{thread, time};
+
+use structopt::StructOpt;
+
+//plays back a recording made with "squares --record <file>"
+#[derive(StructOpt, Debug)]
+#[structopt(name = "replay")]
+struct ReplayArgs {
+    /// The recorded JSONL file
+    #[structopt(parse(from_os_str))]
+    file: PathBuf,
+    /// Address of the squares server
+    #[structopt(long = "url", default_value = "http://localhost:8000")]
+    url: String,
+    /// Token that is sent as "Authorization: Bearer <token>"
+    #[structopt(long = "token")]
+    token: Option<String>,
+    /// 2 plays twice as fast, 0.5 half as fast, 0 sends everything at once
+    #[structopt(long = "speed", default_value = "1.0")]
+    speed: f64,
+}
+
+#[derive(Deserialize)]
+struct Recorded {
+    time: u64,
+    path: String,
+    body: serde_json::Value,
+}
+
+fn main() {
+    let args = ReplayArgs::from_args();
+
+    let file = match File::open(&args.file) {
+        Ok(file) => file,
+        Err(error) => {
+            println!("Could not open {}: {}", args.file.display(), error);
+            return;
+        }
+    };
+
+    let client = reqwest::Client::new();
+    let start = time::Instant::now();
+
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = match line {
+            Ok(line) => line,
+            Err(error) => {
+                println!("Could not read the recording: {}", error);
+                return;
+            }
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let recorded: Recorded = match serde_json::from_str(&line) {
+            Ok(recorded) => recorded,
+            Err(error) => {
+                println!("line {}: {}", number + 1, error);
+                continue;
+            }
+        };
+
+        //waits until the request is due, the time is scaled by the speed
+        if args.speed > 0.0 {
+            let due = time::Duration::from_millis((recorded.time as f64 / args.speed) as u64);
+            let elapsed = start.elapsed();
+            if due > elapsed {
+                thread::sleep(due - elapsed);
+            }
+        }
+
+        let mut request = client
+            .post(&format!("{}{}", args.url, recorded.path))
+            .json(&recorded.body);
+        if let Some(token) = &args.token {
+            request = request.header("Authorization", format!("Bearer {}", token));
+        }
+
+        match request.send() {
+            Ok(mut response) => {
+                let text = response.text().unwrap_or_default();
+                //the server answers with status 200 and the error as text
+                let accepted = text == "\"success\"" || text == "\"scrolling\"";
+                if !response.status().is_success() || !accepted {
+                    println!("line {}: {} {}", number + 1, response.status(), text);
+                }
+            }
+            Err(error) => println!("line {}: {}", number + 1, error),
+        }
+    }
+}